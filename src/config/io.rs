@@ -6,6 +6,7 @@ use directories_next::{ProjectDirs};
 use tokio::fs::{File};
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use serde_json;
+use serde_json::Value;
 use fd_lock::{RwLock, RwLockWriteGuard};
 use std::fs::OpenOptions;
 use std::str;
@@ -14,6 +15,44 @@ use log::{error, info, warn};
 use crate::config::types::Config;
 use crate::error::ConfigError;
 
+/**
+ * Older versions stored the buttons of hotkeys and gestures as four modifier booleans (which were
+ * always the left-hand variants) and one button. Convert those to a chord before the config is parsed.
+ */
+fn migrate_modifier_booleans(config: &mut Value) {
+    const MODIFIERS: [(&str, &str); 4] = [
+        ("modifierShift", "ShiftLeft"),
+        ("modifierCtrl", "ControlLeft"),
+        ("modifierMeta", "MetaLeft"),
+        ("modifierAlt", "Alt"),
+    ];
+
+    for list in ["hotkeys", "gestures"] {
+        let Some(entries) = config.get_mut(list).and_then(Value::as_array_mut) else {
+            continue;
+        };
+
+        for entry in entries.iter_mut().filter_map(Value::as_object_mut) {
+            if entry.contains_key("buttons") {
+                continue;
+            }
+
+            let mut buttons = Vec::new();
+            for (field, modifier) in MODIFIERS {
+                if entry.remove(field).and_then(|value| value.as_bool()).unwrap_or(false) {
+                    buttons.push(Value::from(modifier));
+                }
+            }
+
+            if let Some(button) = entry.remove("button") {
+                buttons.push(button);
+            }
+
+            entry.insert("buttons".to_string(), Value::Array(buttons));
+        }
+    }
+}
+
 // creates a path to CONFIG_FILE_NAME in the same directory as the executable
 // this could be useful for usb sticks
 fn get_portable_config_path() -> Option<PathBuf> {
@@ -141,7 +180,10 @@ impl ConfigIO {
 
         let content = str::from_utf8(&content)?;
 
-        let mut config: Config = serde_json::from_str(content)?;
+        let mut config: Value = serde_json::from_str(content)?;
+        migrate_modifier_booleans(&mut config);
+
+        let mut config: Config = serde_json::from_value(config)?;
         config.sort_hotkeys();
         Ok(config)
     }
@@ -158,3 +200,94 @@ impl ConfigIO {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::config::types::BreathDirection;
+    use crate::sim::types::Button;
+    use super::*;
+
+    #[test]
+    fn modifier_booleans_become_buttons() {
+        let mut config = json!({
+            "hotkeys": [
+                {
+                    "breathDirection": "Puff",
+                    "threshold": 30,
+                    "modifierShift": false,
+                    "modifierCtrl": true,
+                    "modifierMeta": false,
+                    "modifierAlt": true,
+                    "button": "KeyA",
+                },
+                {
+                    "breathDirection": "Sip",
+                    "threshold": 30,
+                    "modifierShift": true,
+                    "button": "MouseLeft",
+                },
+            ],
+            "gestures": [
+                {
+                    "breaths": ["Puff", "Sip"],
+                    "modifierShift": true,
+                    "modifierCtrl": false,
+                    "modifierMeta": true,
+                    "modifierAlt": false,
+                    "button": "Tab",
+                },
+            ],
+        });
+
+        migrate_modifier_booleans(&mut config);
+        assert_eq!(config["hotkeys"][0], json!({
+            "breathDirection": "Puff",
+            "threshold": 30,
+            "buttons": ["ControlLeft", "Alt", "KeyA"],
+        }));
+        assert_eq!(config["hotkeys"][1]["buttons"], json!(["ShiftLeft", "MouseLeft"]));
+        assert_eq!(config["gestures"][0], json!({
+            "breaths": ["Puff", "Sip"],
+            "buttons": ["ShiftLeft", "MetaLeft", "Tab"],
+        }));
+
+        let config: Config = serde_json::from_value(config).unwrap();
+        assert_eq!(config.hotkeys[0].buttons, vec![Button::ControlLeft, Button::Alt, Button::KeyA]);
+        assert_eq!(config.gestures[0].breaths, vec![BreathDirection::Puff, BreathDirection::Sip]);
+        assert_eq!(config.gestures[0].buttons, vec![Button::ShiftLeft, Button::MetaLeft, Button::Tab]);
+    }
+
+    #[test]
+    fn migrated_configs_are_left_alone() {
+        let original = json!({
+            "hotkeys": [
+                {
+                    "breathDirection": "Puff",
+                    "threshold": 30,
+                    "buttons": ["ShiftRight", "KeyB"],
+                },
+                {
+                    "breathDirection": "Sip",
+                    "threshold": 30,
+                    "buttons": [],
+                },
+            ],
+            "gestures": [
+                {
+                    "breaths": ["Sip"],
+                    "buttons": ["Escape"],
+                },
+            ],
+        });
+
+        let mut config = original.clone();
+        migrate_modifier_booleans(&mut config);
+        assert_eq!(config, original);
+
+        // configs without hotkeys or gestures
+        let mut config = json!({});
+        migrate_modifier_booleans(&mut config);
+        assert_eq!(config, json!({}));
+    }
+}
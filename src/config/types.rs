@@ -1,7 +1,10 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
 
-use crate::sim::types::Button;
+use crate::error::MacroParseError;
+use crate::sim::types::{Button, GamepadButton};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BreathDirection {
@@ -25,25 +28,809 @@ impl std::fmt::Display for BreathDirection {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/**
+ * How far the breath strength exceeds the threshold: 0 at the threshold (or below it), 1 at full strength.
+ */
+pub fn strength_above_threshold(breath_strength: i8, threshold: i8) -> f64 {
+    let range = f64::from((100 - i16::from(threshold)).max(1));
+    (f64::from(i16::from(breath_strength) - i16::from(threshold)) / range).clamp(0.0, 1.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SelectionStrategy {
+    // press the hotkey with the highest threshold that the breath has reached so far
+    #[default]
+    Immediate,
+    // wait until the breath stops getting stronger, then press the hotkey that matches the peak.
+    // this avoids pressing the hotkeys with a lower threshold on the way up
+    PeakCommit,
+}
+
+pub const SELECTION_STRATEGIES: [SelectionStrategy; 2] = [
+    SelectionStrategy::Immediate,
+    SelectionStrategy::PeakCommit,
+];
+
+impl std::fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            SelectionStrategy::Immediate => "Immediately",
+            SelectionStrategy::PeakCommit => "At the peak",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+/**
+ * With the PeakCommit strategy, the breath must stop getting stronger for this many milliseconds
+ * before a hotkey is pressed, unless configured otherwise.
+ */
+pub const DEFAULT_PEAK_COMMIT_DELAY: u32 = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MatchingPolicy {
+    // only the hotkey with the highest threshold that matches is active
+    #[default]
+    FirstMatch,
+    // every hotkey that matches is active, and the buttons of all of them are held together
+    AllMatch,
+    // like FirstMatch, but once a hotkey has been pressed, the hotkeys with a lower threshold are
+    // ignored until the breath ends. this avoids pressing them while the breath fades out
+    ExclusiveHighest,
+}
+
+pub const MATCHING_POLICIES: [MatchingPolicy; 3] = [
+    MatchingPolicy::FirstMatch,
+    MatchingPolicy::AllMatch,
+    MatchingPolicy::ExclusiveHighest,
+];
+
+impl std::fmt::Display for MatchingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            MatchingPolicy::FirstMatch => "Highest match",
+            MatchingPolicy::AllMatch => "All matches",
+            MatchingPolicy::ExclusiveHighest => "Highest reached",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HotkeyMode {
+    // hold the button for as long as the hotkey is active
+    #[default]
+    Hold,
+    // press and release the button when the hotkey becomes active
+    TapOnPress,
+    // press and release the button when the hotkey is no longer active
+    TapOnRelease,
+    // press the button when the hotkey becomes active, release it when it becomes active again
+    Latch,
+    // tap the button repeatedly for as long as the hotkey is active, faster as the breath gets stronger
+    Repeat,
+}
+
+pub const HOTKEY_MODES: [HotkeyMode; 5] = [
+    HotkeyMode::Hold,
+    HotkeyMode::TapOnPress,
+    HotkeyMode::TapOnRelease,
+    HotkeyMode::Latch,
+    HotkeyMode::Repeat,
+];
+
+impl std::fmt::Display for HotkeyMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            HotkeyMode::Hold => "Hold",
+            HotkeyMode::TapOnPress => "Tap on press",
+            HotkeyMode::TapOnRelease => "Tap on release",
+            HotkeyMode::Latch => "Latch",
+            HotkeyMode::Repeat => "Repeat",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+/**
+ * How long (milliseconds) a button is pressed when tapping it, unless configured otherwise.
+ */
+pub const DEFAULT_TAP_DURATION: u32 = 50;
+
+/**
+ * Taps per second in the Repeat mode when the breath is at the threshold, unless configured otherwise.
+ */
+pub const DEFAULT_MIN_REPEAT_RATE: u32 = 2;
+
+/**
+ * Taps per second in the Repeat mode at full breath strength, unless configured otherwise.
+ */
+pub const DEFAULT_MAX_REPEAT_RATE: u32 = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MacroStep {
+    // press all buttons in order, then release them
+    Chord(Vec<Button>),
+    // type the characters one by one
+    Text(String),
+    // milliseconds
+    Delay(u32),
+}
+
+impl MacroStep {
+    /**
+     * Parse the notation used in the GUI, for example: `ControlLeft+KeyC, 200ms, "hello"`. Within
+     * text, `\"` is a quote and `\\` is a backslash.
+     */
+    pub fn parse_list(input: &str) -> Result<Vec<MacroStep>, MacroParseError> {
+        let mut steps = Vec::new();
+        let mut rest = input.trim();
+
+        while !rest.is_empty() {
+            if let Some(quoted) = rest.strip_prefix('"') {
+                let (text, after_text) = parse_quoted_text(quoted)?;
+                steps.push(MacroStep::Text(text));
+                rest = after_text.trim_start();
+            }
+            else {
+                let end = rest.find(',').unwrap_or(rest.len());
+                let step = rest[..end].trim();
+
+                if let Some(delay) = step.strip_suffix("ms") {
+                    let delay = delay.trim().parse::<u32>()
+                        .map_err(|_| MacroParseError::InvalidDelay(step.to_string()))?;
+                    steps.push(MacroStep::Delay(delay));
+                }
+                else {
+                    let buttons = step.split('+')
+                        .map(|name| Button::from_name(name.trim()).ok_or_else(|| MacroParseError::UnknownButton(name.trim().to_string())))
+                        .collect::<Result<Vec<Button>, MacroParseError>>()?;
+                    steps.push(MacroStep::Chord(buttons));
+                }
+
+                rest = &rest[end..];
+            }
+
+            rest = match rest.strip_prefix(',') {
+                Some(after_comma) => after_comma.trim_start(),
+                None if rest.is_empty() => rest,
+                None => return Err(MacroParseError::ExpectedComma),
+            };
+        }
+
+        Ok(steps)
+    }
+
+    pub fn format_list(steps: &[MacroStep]) -> String {
+        let steps: Vec<String> = steps.iter().map(|step| match step {
+            MacroStep::Chord(buttons) => {
+                let names: Vec<String> = buttons.iter().map(|button| format!("{:?}", button)).collect();
+                names.join("+")
+            },
+            MacroStep::Text(text) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+            MacroStep::Delay(delay) => format!("{}ms", delay),
+        }).collect();
+
+        steps.join(", ")
+    }
+}
+
+// Parse the text after an opening quote, up to the closing quote. Returns the text and the input
+// after the closing quote
+fn parse_quoted_text(input: &str) -> Result<(String, &str), MacroParseError> {
+    let mut text = String::new();
+    let mut chars = input.char_indices();
+
+    while let Some((index, character)) = chars.next() {
+        match character {
+            '"' => return Ok((text, &input[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('"' | '\\'))) => text.push(escaped),
+                Some((_, escaped)) => return Err(MacroParseError::InvalidEscape(escaped)),
+                None => return Err(MacroParseError::UnterminatedText),
+            },
+            _ => text.push(character),
+        }
+    }
+
+    Err(MacroParseError::UnterminatedText)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HotkeyAction {
+    // press the modifiers and the button of the hotkey
+    #[default]
+    Button,
+    // run a sequence of steps when the hotkey is pressed (or released in the TapOnRelease mode)
+    Macro(Vec<MacroStep>),
+    // scroll for as long as the hotkey is pressed, faster as the breath exceeds the threshold further.
+    // puffs scroll down or right, sips scroll up or left
+    Scroll {
+        horizontal: bool,
+        // notches per second at full breath strength
+        speed: Option<u32>,
+    },
+    // hold a button of the virtual gamepad for as long as the hotkey is pressed, regardless of the mode
+    GamepadButton(GamepadButton),
+}
+
+/**
+ * The maximum number of buttons in the chord of a hotkey or gesture.
+ */
+pub const MAX_CHORD_LENGTH: usize = 4;
+
+/**
+ * How many notches per second a scroll hotkey scrolls at full breath strength, unless configured otherwise.
+ */
+pub const DEFAULT_SCROLL_SPEED: u32 = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HotkeyConfig {
     pub breath_direction: BreathDirection,
     pub threshold: Option<i8>,
-    pub modifier_shift: bool,
-    pub modifier_ctrl: bool,
-    pub modifier_meta: bool,
-    pub modifier_alt: bool,
-    pub button: Button,
+    // if set, the hotkey only matches breaths weaker than this, so that hotkeys can be assigned to bands
+    #[serde(default)]
+    pub upper_threshold: Option<i8>,
+    // once active, the hotkey remains active until the breath strength drops below this value
+    #[serde(default)]
+    pub release_threshold: Option<i8>,
+    // milliseconds that the breath must remain above the threshold before the hotkey is pressed
+    #[serde(default)]
+    pub activation_delay: Option<u32>,
+    // milliseconds that the breath must remain below the release level before the hotkey is released
+    #[serde(default)]
+    pub release_delay: Option<u32>,
+    #[serde(default)]
+    pub mode: HotkeyMode,
+    // milliseconds between press and release, if mode is one of the tap modes
+    #[serde(default)]
+    pub tap_duration: Option<u32>,
+    // taps per second in the Repeat mode, at the threshold and at full breath strength
+    #[serde(default)]
+    pub min_repeat_rate: Option<u32>,
+    #[serde(default)]
+    pub max_repeat_rate: Option<u32>,
+    // milliseconds that the current breath must have lasted before this hotkey can be pressed
+    #[serde(default)]
+    pub min_duration: Option<u32>,
+    // if set, this hotkey is tapped when the breath ends, if the breath lasted at most this many
+    // milliseconds
+    #[serde(default)]
+    pub max_duration: Option<u32>,
+    // seconds that the hotkey may be held continuously, instead of the max_hold_time of the config
+    #[serde(default)]
+    pub max_hold_time: Option<u32>,
+    // pressed in order and released in reverse order, for example [ControlLeft, ShiftLeft, Escape]
+    pub buttons: Vec<Button>,
+    #[serde(default)]
+    pub action: HotkeyAction,
+}
+
+impl HotkeyConfig {
+    /**
+     * Whether the breath strength is below the upper threshold. An upper threshold of 100% or more
+     * includes full strength.
+     */
+    pub fn below_upper_threshold(&self, breath_strength: i8) -> bool {
+        match self.upper_threshold {
+            Some(upper) => breath_strength < upper || upper >= 100,
+            None => true,
+        }
+    }
+
+    /**
+     * Whether the breath strength falls within the band of this hotkey.
+     */
+    pub fn strength_in_range(&self, breath_strength: i8) -> bool {
+        self.threshold.is_some_and(|threshold| breath_strength >= threshold) &&
+            self.below_upper_threshold(breath_strength)
+    }
+
+    /**
+     * Whether a breath could match both hotkeys at the same time: the same direction, overlapping
+     * strength bands and overlapping duration conditions.
+     */
+    pub fn overlaps(&self, other: &HotkeyConfig) -> bool {
+        let (Some(threshold), Some(other_threshold)) = (self.threshold, other.threshold) else {
+            return false;
+        };
+
+        let upper = self.upper_threshold.filter(|upper| *upper < 100).unwrap_or(i8::MAX);
+        let other_upper = other.upper_threshold.filter(|upper| *upper < 100).unwrap_or(i8::MAX);
+        let min_duration = self.min_duration.unwrap_or(0);
+        let max_duration = self.max_duration.unwrap_or(u32::MAX);
+        let other_min_duration = other.min_duration.unwrap_or(0);
+        let other_max_duration = other.max_duration.unwrap_or(u32::MAX);
+
+        self.breath_direction == other.breath_direction &&
+            threshold < other_upper && other_threshold < upper &&
+            min_duration <= other_max_duration && other_min_duration <= max_duration
+    }
+
+    /**
+     * The breath strength below which an active hotkey is released. Never higher than the threshold.
+     */
+    pub fn release_level(&self) -> Option<i8> {
+        self.threshold.map(|threshold| self.release_threshold.unwrap_or(threshold).min(threshold))
+    }
+
+    pub fn activation_delay(&self) -> Duration {
+        Duration::from_millis(u64::from(self.activation_delay.unwrap_or(0)))
+    }
+
+    pub fn release_delay(&self) -> Duration {
+        Duration::from_millis(u64::from(self.release_delay.unwrap_or(0)))
+    }
+
+    pub fn tap_duration(&self) -> Duration {
+        Duration::from_millis(u64::from(self.tap_duration.unwrap_or(DEFAULT_TAP_DURATION)))
+    }
+
+    /**
+     * How long to wait between taps in the Repeat mode, for the given breath strength.
+     */
+    pub fn repeat_interval(&self, breath_strength: i8) -> Duration {
+        let min_rate = f64::from(self.min_repeat_rate.unwrap_or(DEFAULT_MIN_REPEAT_RATE).max(1));
+        let max_rate = f64::from(self.max_repeat_rate.unwrap_or(DEFAULT_MAX_REPEAT_RATE)).max(min_rate);
+        let strength = strength_above_threshold(breath_strength, self.threshold.unwrap_or(0));
+
+        Duration::from_secs_f64(1.0 / (min_rate + (max_rate - min_rate) * strength))
+    }
+
+    pub fn min_duration(&self) -> Option<Duration> {
+        self.min_duration.map(|ms| Duration::from_millis(u64::from(ms)))
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration.map(|ms| Duration::from_millis(u64::from(ms)))
+    }
+
+    /**
+     * How long the hotkey may be held continuously, given the maximum hold time of the config.
+     */
+    pub fn max_hold_time(&self, global_max_hold_time: Option<Duration>) -> Option<Duration> {
+        self.max_hold_time
+            .map(|seconds| Duration::from_secs(u64::from(seconds)))
+            .or(global_max_hold_time)
+    }
+
+    /**
+     * Whether a breath that has lasted (or lasted in total) for the given duration satisfies the
+     * duration conditions of this hotkey.
+     */
+    pub fn duration_matches(&self, duration: Duration) -> bool {
+        let above_min = match self.min_duration() {
+            Some(min) => duration >= min,
+            None => true,
+        };
+        let below_max = match self.max_duration() {
+            Some(max) => duration <= max,
+            None => true,
+        };
+
+        above_min && below_max
+    }
+}
+
+/**
+ * The breath strength (percentage) a breath must reach to be counted as part of a gesture, unless
+ * configured otherwise.
+ */
+pub const DEFAULT_GESTURE_THRESHOLD: i8 = 20;
+
+/**
+ * How long (milliseconds) to wait for the next breath of a gesture, unless configured otherwise.
+ */
+pub const DEFAULT_GESTURE_WINDOW: u32 = 600;
+
+/**
+ * The maximum number of breaths in a gesture.
+ */
+pub const MAX_GESTURE_LENGTH: usize = 5;
+
+/**
+ * The maximum number of gestures in a config.
+ */
+pub const MAX_GESTURES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GestureConfig {
+    // the sequence of breaths that make up this gesture, for example [Sip, Puff]
+    pub breaths: Vec<BreathDirection>,
+    // pressed in order and released in reverse order
+    pub buttons: Vec<Button>,
+}
+
+/**
+ * Breath strength (percentage) at which a puff counts as a dot or dash, unless configured otherwise.
+ */
+pub const DEFAULT_MORSE_THRESHOLD: i8 = 20;
+
+/**
+ * Puffs at least this long (milliseconds) are a dash instead of a dot, unless configured otherwise.
+ */
+pub const DEFAULT_MORSE_DASH_DURATION: u32 = 300;
+
+/**
+ * A pause of this many milliseconds ends the current character, unless configured otherwise.
+ */
+pub const DEFAULT_MORSE_CHARACTER_GAP: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MorseConfig {
+    // if enabled, breath is decoded as morse code and typed, instead of triggering hotkeys
+    pub enabled: bool,
+    pub threshold: Option<i8>,
+    // milliseconds
+    pub dash_duration: Option<u32>,
+    // milliseconds
+    pub character_gap: Option<u32>,
+}
+
+impl MorseConfig {
+    pub fn threshold(&self) -> i8 {
+        self.threshold.unwrap_or(DEFAULT_MORSE_THRESHOLD)
+    }
+
+    pub fn dash_duration(&self) -> Duration {
+        Duration::from_millis(u64::from(self.dash_duration.unwrap_or(DEFAULT_MORSE_DASH_DURATION)))
+    }
+
+    pub fn character_gap(&self) -> Duration {
+        Duration::from_millis(u64::from(self.character_gap.unwrap_or(DEFAULT_MORSE_CHARACTER_GAP)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AccelerationCurve {
+    // the speed increases evenly with the breath strength
+    #[default]
+    Linear,
+    // gentle breaths give fine control, strong breaths move quickly
+    Quadratic,
+    Cubic,
+}
+
+pub const ACCELERATION_CURVES: [AccelerationCurve; 3] = [
+    AccelerationCurve::Linear,
+    AccelerationCurve::Quadratic,
+    AccelerationCurve::Cubic,
+];
+
+impl AccelerationCurve {
+    /**
+     * Maps a breath strength between 0 and 1 to a fraction of the maximum speed.
+     */
+    pub fn apply(&self, strength: f64) -> f64 {
+        let strength = strength.clamp(0.0, 1.0);
+        match self {
+            AccelerationCurve::Linear => strength,
+            AccelerationCurve::Quadratic => strength.powi(2),
+            AccelerationCurve::Cubic => strength.powi(3),
+        }
+    }
+}
+
+impl std::fmt::Display for AccelerationCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            AccelerationCurve::Linear => "Linear",
+            AccelerationCurve::Quadratic => "Quadratic",
+            AccelerationCurve::Cubic => "Cubic",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+/**
+ * Breath strength (percentage) at which the pointer starts moving or rotating, unless configured otherwise.
+ */
+pub const DEFAULT_POINTER_THRESHOLD: i8 = 10;
+
+/**
+ * The distance (pixels) the pointer moves per tick at full breath strength, unless configured otherwise.
+ */
+pub const DEFAULT_POINTER_MAX_SPEED: u32 = 20;
+
+/**
+ * While sipping, the direction of the pointer rotates every this many milliseconds, unless configured otherwise.
+ */
+pub const DEFAULT_POINTER_ROTATION_INTERVAL: u32 = 700;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointerConfig {
+    // if enabled, sipping selects a direction and puffing moves the mouse pointer in that direction,
+    // instead of triggering hotkeys
+    pub enabled: bool,
+    pub threshold: Option<i8>,
+    // pixels per tick
+    pub max_speed: Option<u32>,
+    #[serde(default)]
+    pub acceleration: AccelerationCurve,
+    // milliseconds
+    pub rotation_interval: Option<u32>,
+}
+
+impl PointerConfig {
+    pub fn threshold(&self) -> i8 {
+        self.threshold.unwrap_or(DEFAULT_POINTER_THRESHOLD)
+    }
+
+    pub fn max_speed(&self) -> u32 {
+        self.max_speed.unwrap_or(DEFAULT_POINTER_MAX_SPEED)
+    }
+
+    pub fn rotation_interval(&self) -> Duration {
+        Duration::from_millis(u64::from(self.rotation_interval.unwrap_or(DEFAULT_POINTER_ROTATION_INTERVAL)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GamepadLayout {
+    // puffs move the right trigger, sips move the left trigger
+    #[default]
+    Triggers,
+    // puffs move the stick to the right (or up), sips to the left (or down)
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+}
+
+pub const GAMEPAD_LAYOUTS: [GamepadLayout; 5] = [
+    GamepadLayout::Triggers,
+    GamepadLayout::LeftStickX,
+    GamepadLayout::LeftStickY,
+    GamepadLayout::RightStickX,
+    GamepadLayout::RightStickY,
+];
+
+impl std::fmt::Display for GamepadLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            GamepadLayout::Triggers => "Triggers (puff right, sip left)",
+            GamepadLayout::LeftStickX => "Left stick (puff right)",
+            GamepadLayout::LeftStickY => "Left stick (puff up)",
+            GamepadLayout::RightStickX => "Right stick (puff right)",
+            GamepadLayout::RightStickY => "Right stick (puff up)",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+/**
+ * Breath strength (percentage) below which the gamepad axis stays at rest, unless configured otherwise.
+ */
+pub const DEFAULT_GAMEPAD_DEADZONE: i8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamepadConfig {
+    // if enabled, a virtual gamepad follows the breath strength, in addition to the hotkeys (Linux only)
+    pub enabled: bool,
+    #[serde(default)]
+    pub layout: GamepadLayout,
+    pub deadzone: Option<i8>,
+    #[serde(default)]
+    pub response_curve: AccelerationCurve,
+}
+
+impl GamepadConfig {
+    pub fn deadzone(&self) -> i8 {
+        self.deadzone.unwrap_or(DEFAULT_GAMEPAD_DEADZONE)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Smoothing {
+    #[default]
+    Off,
+    // the average of the most recent samples
+    MovingAverage,
+    // each sample moves the value part of the way, recent samples weigh the most
+    Exponential,
+}
+
+pub const SMOOTHINGS: [Smoothing; 3] = [
+    Smoothing::Off,
+    Smoothing::MovingAverage,
+    Smoothing::Exponential,
+];
+
+impl std::fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            Smoothing::Off => "Off",
+            Smoothing::MovingAverage => "Moving average",
+            Smoothing::Exponential => "Exponential",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+/**
+ * The number of samples (10 milliseconds each) that smoothing averages over, unless configured otherwise.
+ */
+pub const DEFAULT_SMOOTHING_WINDOW: u32 = 4;
+
+/**
+ * Breath values (percentage) closer to neutral than this are treated as neutral, unless configured otherwise.
+ */
+pub const DEFAULT_BREATH_DEADZONE: i8 = 0;
+
+/**
+ * Breath values are multiplied by this percentage before the response curve is applied, unless
+ * configured otherwise.
+ */
+pub const DEFAULT_BREATH_SENSITIVITY: u32 = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterConfig {
+    #[serde(default)]
+    pub smoothing: Smoothing,
+    // samples
+    pub smoothing_window: Option<u32>,
+    // if enabled, a single sample that differs from its neighbours is ignored
+    #[serde(default)]
+    pub spike_filter: bool,
+    pub deadzone: Option<i8>,
+    #[serde(default)]
+    pub puff_curve: AccelerationCurve,
+    #[serde(default)]
+    pub sip_curve: AccelerationCurve,
+    // percentage
+    pub puff_sensitivity: Option<u32>,
+    pub sip_sensitivity: Option<u32>,
+}
+
+impl FilterConfig {
+    pub fn smoothing_window(&self) -> usize {
+        self.smoothing_window.unwrap_or(DEFAULT_SMOOTHING_WINDOW).max(1) as usize
+    }
+
+    pub fn deadzone(&self) -> i8 {
+        self.deadzone.unwrap_or(DEFAULT_BREATH_DEADZONE)
+    }
+
+    pub fn curve(&self, direction: BreathDirection) -> AccelerationCurve {
+        match direction {
+            BreathDirection::Puff => self.puff_curve,
+            BreathDirection::Sip => self.sip_curve,
+        }
+    }
+
+    pub fn sensitivity(&self, direction: BreathDirection) -> u32 {
+        let sensitivity = match direction {
+            BreathDirection::Puff => self.puff_sensitivity,
+            BreathDirection::Sip => self.sip_sensitivity,
+        };
+
+        sensitivity.unwrap_or(DEFAULT_BREATH_SENSITIVITY)
+    }
+}
+
+/**
+ * A breath that gets this much stronger (percentage) within 10 milliseconds might be an artifact,
+ * unless configured otherwise.
+ */
+pub const DEFAULT_ARTIFACT_STEEPNESS: i8 = 30;
+
+/**
+ * A steep breath that lasts longer than this many milliseconds is not an artifact, unless configured otherwise.
+ */
+pub const DEFAULT_ARTIFACT_MAX_DURATION: u32 = 150;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactConfig {
+    // if enabled, short and very steep breaths (such as coughs, sneezes and talking) are ignored
+    pub enabled: bool,
+    // percentage per 10 milliseconds
+    pub steepness: Option<i8>,
+    // milliseconds
+    pub max_duration: Option<u32>,
+}
+
+impl ArtifactConfig {
+    pub fn steepness(&self) -> i8 {
+        self.steepness.unwrap_or(DEFAULT_ARTIFACT_STEEPNESS)
+    }
+
+    pub fn max_duration(&self) -> Duration {
+        Duration::from_millis(u64::from(self.max_duration.unwrap_or(DEFAULT_ARTIFACT_MAX_DURATION)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationConfig {
+    // if enabled, neutral slowly follows the breath value while it stays close to neutral
+    #[serde(default)]
+    pub auto_zero: bool,
+    // how far (raw device units) the neutral breath value of each device is from BREATH_RANGE,
+    // by device id
+    #[serde(default)]
+    pub offsets: BTreeMap<String, i16>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub hotkeys: Vec<HotkeyConfig>,
+    // release all buttons if they have been held for this many seconds
+    #[serde(default)]
+    pub max_hold_time: Option<u32>,
+    // tapped when the breath matches a sequence of breaths
+    #[serde(default)]
+    pub gestures: Vec<GestureConfig>,
+    #[serde(default)]
+    pub gesture_threshold: Option<i8>,
+    // milliseconds
+    #[serde(default)]
+    pub gesture_window: Option<u32>,
+    #[serde(default)]
+    pub morse: MorseConfig,
+    #[serde(default)]
+    pub pointer: PointerConfig,
+    #[serde(default)]
+    pub gamepad: GamepadConfig,
+    // how a hotkey is chosen while the breath is getting stronger, for puffs and sips separately
+    #[serde(default)]
+    pub puff_selection: SelectionStrategy,
+    #[serde(default)]
+    pub sip_selection: SelectionStrategy,
+    // milliseconds
+    #[serde(default)]
+    pub peak_commit_delay: Option<u32>,
+    // which of the hotkeys that match the breath are active, for puffs and sips separately
+    #[serde(default)]
+    pub puff_matching: MatchingPolicy,
+    #[serde(default)]
+    pub sip_matching: MatchingPolicy,
+    // applied to the breath values of the device, before they are used for anything else
+    #[serde(default)]
+    pub filter: FilterConfig,
+    #[serde(default)]
+    pub artifacts: ArtifactConfig,
+    // applied to the raw values of the device, before they are converted to a percentage
+    #[serde(default)]
+    pub calibration: CalibrationConfig,
 }
 
 impl Config {
+    /**
+     * Pairs of hotkeys (indices into `hotkeys`) whose bands overlap. Only hotkeys with an upper
+     * threshold are considered, because open ended hotkeys are meant to be stacked: the highest
+     * threshold that has been reached wins.
+     */
+    pub fn overlapping_hotkeys(&self) -> Vec<(usize, usize)> {
+        let mut overlapping = Vec::new();
+
+        for (index, hotkey) in self.hotkeys.iter().enumerate() {
+            for (other_index, other) in self.hotkeys.iter().enumerate().skip(index + 1) {
+                let has_band = hotkey.upper_threshold.is_some() || other.upper_threshold.is_some();
+                if has_band && hotkey.overlaps(other) {
+                    overlapping.push((index, other_index));
+                }
+            }
+        }
+
+        overlapping
+    }
+
     pub fn sort_hotkeys(&mut self) {
         self.hotkeys.sort_by(|a, b| {
             let a_sip = a.breath_direction == BreathDirection::Sip;
@@ -55,6 +842,22 @@ impl Config {
             a.threshold.unwrap_or(i8::max_value()).cmp(&b.threshold.unwrap_or(i8::max_value()))
         })
     }
+
+    pub fn max_hold_time(&self) -> Option<Duration> {
+        self.max_hold_time.map(|seconds| Duration::from_secs(u64::from(seconds)))
+    }
+
+    pub fn peak_commit_delay(&self) -> Duration {
+        Duration::from_millis(u64::from(self.peak_commit_delay.unwrap_or(DEFAULT_PEAK_COMMIT_DELAY)))
+    }
+
+    pub fn gesture_threshold(&self) -> i8 {
+        self.gesture_threshold.unwrap_or(DEFAULT_GESTURE_THRESHOLD)
+    }
+
+    pub fn gesture_window(&self) -> Duration {
+        Duration::from_millis(u64::from(self.gesture_window.unwrap_or(DEFAULT_GESTURE_WINDOW)))
+    }
 }
 
 impl Default for Config {
@@ -64,22 +867,82 @@ impl Default for Config {
                 HotkeyConfig {
                     breath_direction: BreathDirection::Sip,
                     threshold: Some(8),
-                    modifier_shift: false,
-                    modifier_ctrl: false,
-                    modifier_meta: false,
-                    modifier_alt: false,
-                    button: Button::MouseRight,
+                    upper_threshold: None,
+                    release_threshold: None,
+                    activation_delay: None,
+                    release_delay: None,
+                    mode: HotkeyMode::Hold,
+                    tap_duration: None,
+                    min_repeat_rate: None,
+                    max_repeat_rate: None,
+                    min_duration: None,
+                    max_duration: None,
+                    max_hold_time: None,
+                    buttons: vec![Button::MouseRight],
+                    action: HotkeyAction::Button,
                 },
                 HotkeyConfig {
                     breath_direction: BreathDirection::Puff,
                     threshold: Some(7),
-                    modifier_shift: false,
-                    modifier_ctrl: false,
-                    modifier_meta: false,
-                    modifier_alt: false,
-                    button: Button::MouseLeft,
+                    upper_threshold: None,
+                    release_threshold: None,
+                    activation_delay: None,
+                    release_delay: None,
+                    mode: HotkeyMode::Hold,
+                    tap_duration: None,
+                    min_repeat_rate: None,
+                    max_repeat_rate: None,
+                    min_duration: None,
+                    max_duration: None,
+                    max_hold_time: None,
+                    buttons: vec![Button::MouseLeft],
+                    action: HotkeyAction::Button,
                 },
             ],
+            max_hold_time: None,
+            gestures: Vec::new(),
+            gesture_threshold: None,
+            gesture_window: None,
+            morse: MorseConfig::default(),
+            pointer: PointerConfig::default(),
+            gamepad: GamepadConfig::default(),
+            puff_selection: SelectionStrategy::Immediate,
+            sip_selection: SelectionStrategy::Immediate,
+            peak_commit_delay: None,
+            puff_matching: MatchingPolicy::FirstMatch,
+            sip_matching: MatchingPolicy::FirstMatch,
+            filter: FilterConfig::default(),
+            artifacts: ArtifactConfig::default(),
+            calibration: CalibrationConfig::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macro_text_escapes_round_trip() {
+        let steps = vec![
+            MacroStep::Chord(vec![Button::ControlLeft, Button::KeyC]),
+            MacroStep::Text(r#"say "hi" in C:\temp\"#.to_string()),
+            MacroStep::Delay(200),
+        ];
+
+        let formatted = MacroStep::format_list(&steps);
+        assert_eq!(formatted, r#"ControlLeft+KeyC, "say \"hi\" in C:\\temp\\", 200ms"#);
+        assert_eq!(MacroStep::parse_list(&formatted).unwrap(), steps);
+    }
+
+    #[test]
+    fn macro_text_rejects_unknown_escapes() {
+        assert!(matches!(MacroStep::parse_list(r#""a\nb""#), Err(MacroParseError::InvalidEscape('n'))));
+        assert!(matches!(MacroStep::parse_list(r#""a\""#), Err(MacroParseError::UnterminatedText)));
+        assert_eq!(
+            MacroStep::parse_list(r#""a\\", KeyA"#).unwrap(),
+            vec![MacroStep::Text("a\\".to_string()), MacroStep::Chord(vec![Button::KeyA])],
+        );
+    }
+}
+
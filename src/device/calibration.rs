@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+use log::info;
+use tokio::time::{Duration, Instant};
+
+use crate::config::types::CalibrationConfig;
+use crate::device::constants::BREATH_RANGE;
+
+/**
+ * With auto zero, neutral is only adjusted while the breath stays within this many raw device units
+ * (about 4%) of neutral.
+ */
+const AUTO_ZERO_RANGE: f32 = 80.0;
+
+/**
+ * With auto zero, neutral is only adjusted once the breath has stayed close to neutral for this
+ * many milliseconds.
+ */
+const AUTO_ZERO_DELAY: u64 = 3000;
+
+/**
+ * With auto zero, neutral moves most (63%) of the way to the idle breath value in this many seconds.
+ */
+const AUTO_ZERO_TIME_CONSTANT: f32 = 20.0;
+
+/**
+ * Auto zero adjusts the offset a little with nearly every breath value. The adjusted offset is only
+ * stored in the config every this many seconds, and when the device disconnects.
+ */
+const AUTO_ZERO_STORE_INTERVAL: u64 = 300;
+
+#[derive(Default)]
+struct CalibrationState {
+    auto_zero: bool,
+    // raw device units, by device id
+    offsets: BTreeMap<String, i16>,
+    // true if offsets has changed since the last call to take_changed_offsets()
+    offsets_changed: bool,
+    // the id of the connected device, and its offset with more precision than the stored offset
+    device: Option<(String, f32)>,
+    // when the offset of the connected device was last stored in offsets
+    stored_at: Option<Instant>,
+    // the next breath value is used as the new neutral
+    zero_requested: bool,
+    // the breath has stayed close to neutral since this instant
+    idle_since: Option<Instant>,
+    previous_sample_at: Option<Instant>,
+}
+
+/**
+ * The neutral breath value of every device. This is shared between the task that reads the breath
+ * values of the device, which applies the offset and adjusts it, and the GUI, which stores the
+ * offsets in the config.
+ */
+#[derive(Default)]
+pub struct Calibration {
+    state: Mutex<CalibrationState>,
+}
+
+impl Calibration {
+    fn lock(&self) -> MutexGuard<'_, CalibrationState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /**
+     * Use the settings and offsets of the config. Offsets that have changed since they were last
+     * taken are kept, so that they are not overwritten by an older config. The offset of the
+     * connected device keeps its adjustments by auto zero, unless the config changes it.
+     */
+    pub fn set_config(&self, config: &CalibrationConfig) {
+        let mut state = self.lock();
+        state.auto_zero = config.auto_zero;
+
+        if state.offsets_changed {
+            return;
+        }
+
+        let new_device_offset = state.device.as_ref().and_then(|(device_id, _)| {
+            let new_offset = config.offsets.get(device_id).copied().unwrap_or(0);
+            let previous_offset = state.offsets.get(device_id).copied().unwrap_or(0);
+            (new_offset != previous_offset).then_some(new_offset)
+        });
+
+        state.offsets = config.offsets.clone();
+        if let (Some(new_offset), Some((_, offset))) = (new_device_offset, &mut state.device) {
+            *offset = f32::from(new_offset);
+        }
+    }
+
+    /**
+     * Returns the offsets of all devices if any of them has changed since the previous call.
+     */
+    pub fn take_changed_offsets(&self) -> Option<BTreeMap<String, i16>> {
+        let mut state = self.lock();
+        if !state.offsets_changed {
+            return None;
+        }
+
+        state.offsets_changed = false;
+        Some(state.offsets.clone())
+    }
+
+    /**
+     * The offset (raw device units) of the connected device, if any.
+     */
+    pub fn offset(&self) -> Option<f32> {
+        self.lock().device.as_ref().map(|(_, offset)| *offset)
+    }
+
+    pub fn connect(&self, device_id: String) {
+        let mut state = self.lock();
+        let offset = state.offsets.get(&device_id).copied().unwrap_or(0);
+        info!("Device {} is calibrated with an offset of {}", device_id, offset);
+
+        state.device = Some((device_id, f32::from(offset)));
+        state.stored_at = None;
+        state.zero_requested = false;
+        state.idle_since = None;
+        state.previous_sample_at = None;
+    }
+
+    pub fn disconnect(&self) {
+        let mut state = self.lock();
+        // keep the adjustments by auto zero
+        store_offset(&mut state);
+        state.device = None;
+        state.zero_requested = false;
+    }
+
+    /**
+     * Use the next breath value of the connected device as neutral.
+     */
+    pub fn zero(&self) {
+        let mut state = self.lock();
+        if state.device.is_some() {
+            state.zero_requested = true;
+        }
+    }
+
+    /**
+     * Forget the offset of the connected device.
+     */
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        if let Some((device_id, offset)) = &mut state.device {
+            info!("Removing the calibration of device {}", device_id);
+            *offset = 0.0;
+        }
+        state.zero_requested = false;
+        state.idle_since = None;
+        store_offset(&mut state);
+    }
+
+    /**
+     * Returns the raw breath value of the connected device, shifted so that its neutral is at
+     * BREATH_RANGE. Adjusts the offset if zeroing has been requested, or if the breath has been
+     * idle while auto zero is enabled.
+     */
+    pub fn apply(&self, raw_value: f32, now: Instant) -> f32 {
+        let mut state = self.lock();
+        let previous_sample_at = state.previous_sample_at.replace(now);
+        let zero_requested = std::mem::take(&mut state.zero_requested);
+        let auto_zero = state.auto_zero;
+        let mut idle_since = state.idle_since;
+
+        let Some((device_id, offset)) = &mut state.device else {
+            return raw_value;
+        };
+
+        // how far the breath is from the current neutral
+        let deviation = raw_value - f32::from(BREATH_RANGE) - *offset;
+        let mut store_now = false;
+
+        if zero_requested {
+            info!("Zeroing device {}, neutral moves by {} from {}", device_id, deviation, *offset);
+            *offset += deviation;
+            idle_since = None;
+            store_now = true;
+        }
+        else if auto_zero && deviation.abs() <= AUTO_ZERO_RANGE {
+            let since = *idle_since.get_or_insert(now);
+            if now - since >= Duration::from_millis(AUTO_ZERO_DELAY) {
+                let elapsed = previous_sample_at.map(|at| now - at).unwrap_or_default().as_secs_f32();
+                *offset += deviation * (elapsed / AUTO_ZERO_TIME_CONSTANT).min(1.0);
+            }
+        }
+        else {
+            idle_since = None;
+        }
+
+        let corrected_value = raw_value - *offset;
+        state.idle_since = idle_since;
+
+        let stored_at = *state.stored_at.get_or_insert(now);
+        if store_now || now - stored_at >= Duration::from_secs(AUTO_ZERO_STORE_INTERVAL) {
+            state.stored_at = Some(now);
+            store_offset(&mut state);
+        }
+        corrected_value
+    }
+}
+
+// Store the offset of the connected device, if it has changed by at least one unit
+fn store_offset(state: &mut CalibrationState) {
+    let Some((device_id, offset)) = &state.device else {
+        return;
+    };
+
+    let offset = offset.round() as i16;
+    if state.offsets.get(device_id).copied().unwrap_or(0) == offset {
+        return;
+    }
+
+    if offset == 0 {
+        state.offsets.remove(device_id);
+    }
+    else {
+        state.offsets.insert(device_id.clone(), offset);
+    }
+    state.offsets_changed = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A calibration with auto zero, connected to device "a"
+    fn auto_zero_calibration() -> Calibration {
+        let calibration = Calibration::default();
+        calibration.set_config(&CalibrationConfig { auto_zero: true, offsets: BTreeMap::new() });
+        calibration.connect("a".to_string());
+        calibration
+    }
+
+    // Apply the same raw value once per second, for the given number of seconds
+    fn apply_idle(calibration: &Calibration, start: Instant, seconds: u64) {
+        for second in 0..=seconds {
+            calibration.apply(f32::from(BREATH_RANGE) + 40.0, start + Duration::from_secs(second));
+        }
+    }
+
+    #[test]
+    fn auto_zero_is_stored_at_an_interval() {
+        let calibration = auto_zero_calibration();
+        let start = Instant::now();
+
+        apply_idle(&calibration, start, AUTO_ZERO_STORE_INTERVAL - 1);
+        assert!(calibration.offset().is_some_and(|offset| offset > 30.0));
+        assert_eq!(calibration.take_changed_offsets(), None);
+
+        calibration.apply(f32::from(BREATH_RANGE) + 40.0, start + Duration::from_secs(AUTO_ZERO_STORE_INTERVAL));
+        assert_eq!(calibration.take_changed_offsets(), Some(BTreeMap::from([("a".to_string(), 40)])));
+    }
+
+    #[test]
+    fn auto_zero_is_stored_on_disconnect() {
+        let calibration = auto_zero_calibration();
+
+        apply_idle(&calibration, Instant::now(), 30);
+        assert_eq!(calibration.take_changed_offsets(), None);
+
+        calibration.disconnect();
+        assert!(calibration.take_changed_offsets().is_some_and(|offsets| offsets.get("a").is_some_and(|offset| *offset > 10)));
+    }
+
+    #[test]
+    fn auto_zero_survives_config_changes() {
+        let calibration = auto_zero_calibration();
+
+        apply_idle(&calibration, Instant::now(), 30);
+        let offset = calibration.offset();
+        calibration.set_config(&CalibrationConfig { auto_zero: true, offsets: BTreeMap::new() });
+        assert_eq!(calibration.offset(), offset);
+
+        calibration.set_config(&CalibrationConfig { auto_zero: true, offsets: BTreeMap::from([("a".to_string(), 5)]) });
+        assert_eq!(calibration.offset(), Some(5.0));
+    }
+
+    #[test]
+    fn zero_is_stored_immediately() {
+        let calibration = Calibration::default();
+        calibration.connect("a".to_string());
+
+        calibration.zero();
+        calibration.apply(f32::from(BREATH_RANGE) + 100.0, Instant::now());
+        assert_eq!(calibration.take_changed_offsets(), Some(BTreeMap::from([("a".to_string(), 100)])));
+
+        calibration.reset();
+        assert_eq!(calibration.take_changed_offsets(), Some(BTreeMap::new()));
+    }
+}
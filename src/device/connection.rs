@@ -1,7 +1,8 @@
 use std::convert::Infallible;
 use std::error::Error;
-use iced::subscription::{self, Subscription};
+use std::sync::Arc;
 use futures::{StreamExt, SinkExt};
+use futures::future::BoxFuture;
 use futures::channel::mpsc::Sender;
 use btleplug::api::{Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType};
 use btleplug::platform::{Adapter, Manager, Peripheral};
@@ -9,9 +10,11 @@ use log::{debug, info, warn};
 use tokio::spawn;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
 
+use crate::device::calibration::Calibration;
 use crate::device::constants::{make_melody_smart_service_uuid, make_melody_smart_data_uuid, CONNECT_DELAY, POLL_DELAY, COMMAND_REQUEST_BREATH, BREATH_RANGE, IS_CONNECTED_DEADLINE, WRITE_DEADLINE, COMMAND_LED_LEFT_ON};
+use crate::device::source::BreathSource;
 use crate::device::types::{DeviceEvent, DeviceState};
 use crate::error::DeviceError;
 
@@ -234,12 +237,19 @@ async fn request_breath(peripheral: &Peripheral, data_char: &Characteristic) {
     };
 }
 
-fn read_notifications_task(cancel: CancellationToken, peripheral: &Peripheral, mut senders: Vec<Sender<DeviceEvent>>) -> JoinHandle<Result<(), DeviceError>> {
+fn read_notifications_task(
+    cancel: CancellationToken,
+    peripheral: &Peripheral,
+    mut senders: Vec<Sender<DeviceEvent>>,
+    calibration: Arc<Calibration>,
+) -> JoinHandle<Result<(), DeviceError>> {
     let peripheral_clone = peripheral.clone();
     let melody_smart_data_uuid = make_melody_smart_data_uuid();
 
     return spawn(async move {
         let mut notification_stream = peripheral_clone.notifications().await?;
+        calibration.connect(peripheral_clone.id().to_string());
+
         let mut previous_value: i8 = 0;
 
         'mainloop: loop {
@@ -261,11 +271,13 @@ fn read_notifications_task(cancel: CancellationToken, peripheral: &Peripheral, m
                                 let value = f32::try_from(parsed)
                                     .unwrap_or(range) // default to neutral (2048)
                                     .clamp(0.0_f32, range * 2.0_f32); // clamp to [0, 4096]
+                                let value = calibration.apply(value, Instant::now()); // the neutral of this device is now 2048
                                 let value =
                                     (value - range) // normalize to [-2048, 2048]; 0 is now neutral
                                     / range // convert to a factor: [-1, 1]
                                     * 100.0_f32; // convert to a percentage
                                 let value = value
+                                    .clamp(-100.0_f32, 100.0_f32) // the calibration might have moved it out of range
                                     .round() as i8; // convert to an integer number, this also helps to avoid unnecessary updates
 
                                 if previous_value != value {
@@ -281,11 +293,12 @@ fn read_notifications_task(cancel: CancellationToken, peripheral: &Peripheral, m
             }
         }
 
+        calibration.disconnect();
         Ok(())
     });
 }
 
-async fn connect_device(cancel: CancellationToken, mut senders: Vec<Sender<DeviceEvent>>) -> Infallible {
+async fn connect_device(cancel: CancellationToken, mut senders: Vec<Sender<DeviceEvent>>, calibration: Arc<Calibration>) -> Infallible {
     let mut connection_state = Some(ConnectionState::Scanning { adapters: None, retry: false, no_permission: false });
     let mut previous_device_state: Option<DeviceState> = None;
     let mut read_notifications_task_handle: Option<JoinHandle<Result<(), DeviceError>>> = None;
@@ -321,7 +334,7 @@ async fn connect_device(cancel: CancellationToken, mut senders: Vec<Sender<Devic
                 // Connected, start task to read notifications if not already started
                 // and send ?b commands to the device every 10ms
                 read_notifications_task_handle.get_or_insert_with(
-                    || read_notifications_task(connection_cancel.clone(), peripheral, senders.clone())
+                    || read_notifications_task(connection_cancel.clone(), peripheral, senders.clone(), calibration.clone())
                 );
                 request_breath(peripheral, data_char).await;
             },
@@ -344,20 +357,15 @@ async fn connect_device(cancel: CancellationToken, mut senders: Vec<Sender<Devic
     }
 }
 
-pub fn connect_device_subscription(cancel: CancellationToken, senders: Vec<Sender<DeviceEvent>>) -> Subscription<DeviceEvent> {
-    struct Connect;
-
-    subscription::channel(
-        std::any::TypeId::of::<Connect>(),
-        64,
-        move |subscription_sender| {
-            let cancel2 = cancel.clone();
-            let mut senders2 = senders.clone();
-            senders2.push(subscription_sender);
+pub struct BluetoothBreathSource;
 
-            async move {
-                connect_device(cancel2, senders2).await
-            }
-        },
-    )
+impl BreathSource for BluetoothBreathSource {
+    fn run(
+        &self,
+        cancel: CancellationToken,
+        senders: Vec<Sender<DeviceEvent>>,
+        calibration: Arc<Calibration>,
+    ) -> BoxFuture<'static, Infallible> {
+        Box::pin(connect_device(cancel, senders, calibration))
+    }
 }
@@ -1,3 +1,6 @@
+pub mod calibration;
 pub mod connection;
 pub mod constants;
-pub mod types;
\ No newline at end of file
+pub mod simulated;
+pub mod source;
+pub mod types;
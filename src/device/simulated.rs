@@ -0,0 +1,127 @@
+use std::convert::Infallible;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use clap::ValueEnum;
+use futures::SinkExt;
+use futures::channel::mpsc::Sender;
+use futures::future::{pending, BoxFuture};
+use log::{debug, info};
+use tokio_util::sync::CancellationToken;
+use tokio::time::{sleep, Duration, Instant};
+
+use crate::device::calibration::Calibration;
+use crate::device::constants::{CONNECT_DELAY, POLL_DELAY};
+use crate::device::source::BreathSource;
+use crate::device::types::{DeviceEvent, DeviceState};
+
+/**
+ * The breath values (percentage) that the Step curve cycles through.
+ */
+const STEP_VALUES: [i8; 8] = [0, 25, 50, 75, 0, -25, -50, -75];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SimulatedCurve {
+    /// Smoothly alternate between puffing and sipping at full strength
+    Sine,
+    /// Linearly increase from full sip to full puff, then start over
+    Ramp,
+    /// Hold a series of fixed puff and sip strengths
+    Step,
+}
+
+impl SimulatedCurve {
+    /**
+     * Returns the breath value [-100, 100] for the given position within the period of the curve.
+     * `phase` is a value between 0 (inclusive) and 1 (exclusive).
+     */
+    pub fn value(&self, phase: f32) -> i8 {
+        let value = match self {
+            SimulatedCurve::Sine => (phase * 2.0 * PI).sin() * 100.0,
+            SimulatedCurve::Ramp => phase * 200.0 - 100.0,
+            SimulatedCurve::Step => {
+                let index = (phase * STEP_VALUES.len() as f32) as usize;
+                STEP_VALUES[index.min(STEP_VALUES.len() - 1)] as f32
+            },
+        };
+
+        value.round().clamp(-100.0, 100.0) as i8
+    }
+}
+
+/**
+ * A BreathSource that does not require any hardware, useful for demos and development.
+ */
+pub struct SimulatedBreathSource {
+    curve: SimulatedCurve,
+    period: Duration,
+}
+
+impl SimulatedBreathSource {
+    pub fn new(curve: SimulatedCurve, period: Duration) -> Self {
+        SimulatedBreathSource { curve, period }
+    }
+}
+
+// Send the event to every receiver that is still listening, and drop the senders of the
+// receivers that are gone
+async fn send_event(senders: &mut Vec<Sender<DeviceEvent>>, event: DeviceEvent) {
+    for sender in senders.iter_mut() {
+        if sender.send(event.clone()).await.is_err() {
+            debug!("DeviceEvent receiver has been closed");
+        }
+    }
+
+    senders.retain(|sender| !sender.is_closed());
+}
+
+async fn simulate_device(
+    curve: SimulatedCurve,
+    period: Duration,
+    cancel: CancellationToken,
+    mut senders: Vec<Sender<DeviceEvent>>,
+) -> Infallible {
+    info!("Simulating a device using the {:?} curve with a period of {:?}", curve, period);
+
+    // mimic the states of a real device, so that the GUI behaves the same
+    send_event(&mut senders, DeviceEvent::StateChange(DeviceState::Scanning { no_permission: false })).await;
+    sleep(Duration::from_millis(CONNECT_DELAY)).await;
+    send_event(&mut senders, DeviceEvent::StateChange(DeviceState::Connecting)).await;
+    sleep(Duration::from_millis(CONNECT_DELAY)).await;
+    send_event(&mut senders, DeviceEvent::StateChange(DeviceState::Connected)).await;
+
+    let start = Instant::now();
+    let period_secs = period.as_secs_f32().max(f32::EPSILON);
+    let mut previous_value: i8 = 0;
+
+    'mainloop: loop {
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                break 'mainloop;
+            },
+            _ = sleep(Duration::from_millis(POLL_DELAY)) => {
+                let phase = (start.elapsed().as_secs_f32() % period_secs) / period_secs;
+                let value = curve.value(phase);
+
+                if previous_value != value {
+                    previous_value = value;
+                    send_event(&mut senders, DeviceEvent::Breath(value)).await;
+                }
+            },
+        }
+    }
+
+    // note: subscription::channel expects the future to never resolve (Infallible)
+    pending().await
+}
+
+impl BreathSource for SimulatedBreathSource {
+    // the simulated breath values are exact, so they are not calibrated
+    fn run(
+        &self,
+        cancel: CancellationToken,
+        senders: Vec<Sender<DeviceEvent>>,
+        _calibration: Arc<Calibration>,
+    ) -> BoxFuture<'static, Infallible> {
+        Box::pin(simulate_device(self.curve, self.period, cancel, senders))
+    }
+}
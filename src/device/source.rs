@@ -0,0 +1,48 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use futures::channel::mpsc::Sender;
+use futures::future::BoxFuture;
+use iced::subscription::{self, Subscription};
+use tokio_util::sync::CancellationToken;
+
+use crate::device::calibration::Calibration;
+use crate::device::types::DeviceEvent;
+
+/**
+ * Something that produces breath values, such as a GroovTube connected using bluetooth, or a
+ * simulated device.
+ */
+pub trait BreathSource: Send + Sync {
+    /**
+     * Emit DeviceEvent's to all the given senders. A StateChange to DeviceState::Connected must be
+     * sent before any Breath events.
+     * Sources that report raw device values apply `calibration` to them.
+     * The returned future should never resolve, even after `cancel` has been cancelled.
+     */
+    fn run(
+        &self,
+        cancel: CancellationToken,
+        senders: Vec<Sender<DeviceEvent>>,
+        calibration: Arc<Calibration>,
+    ) -> BoxFuture<'static, Infallible>;
+}
+
+pub fn breath_source_subscription(
+    source: Arc<dyn BreathSource>,
+    cancel: CancellationToken,
+    senders: Vec<Sender<DeviceEvent>>,
+    calibration: Arc<Calibration>,
+) -> Subscription<DeviceEvent> {
+    struct Connect;
+
+    subscription::channel(
+        std::any::TypeId::of::<Connect>(),
+        64,
+        move |subscription_sender| {
+            let mut senders2 = senders.clone();
+            senders2.push(subscription_sender);
+
+            source.run(cancel.clone(), senders2, calibration.clone())
+        },
+    )
+}
@@ -31,6 +31,24 @@ pub enum ConfigError {
     SendError { #[from] source: SendError },
 }
 
+#[derive(Error, Debug)]
+pub enum MacroParseError {
+    #[error("Missing closing quote")]
+    UnterminatedText,
+
+    #[error("Invalid escape sequence: \\{0}, use \\\" for a quote and \\\\ for a backslash")]
+    InvalidEscape(char),
+
+    #[error("Invalid delay: {0}")]
+    InvalidDelay(String),
+
+    #[error("Unknown button: {0}")]
+    UnknownButton(String),
+
+    #[error("Expected a comma between steps")]
+    ExpectedComma,
+}
+
 #[derive(Error, Debug)]
 pub enum LinkOpenError {
     #[error("Failed to open link: {source}")]
@@ -56,6 +74,12 @@ pub enum AppRunError {
 
     #[error("Failed to start application (config): {source}")]
     ConfigError { #[from] source: ConfigError },
+
+    #[error("Failed to open the recording file: {source}")]
+    RecordingError { source: io::Error },
+
+    #[error("Failed to set up input simulation: {source}")]
+    InputBackendError { source: io::Error },
 }
 
 #[derive(Error, Debug)]
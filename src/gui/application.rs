@@ -1,4 +1,4 @@
-use futures::channel::mpsc::Sender;
+use futures::channel::mpsc::{Receiver, Sender};
 use futures::SinkExt;
 use iced::{Alignment, Application, Command, Element, Length, Settings, Size, Subscription, window};
 use iced::event::{self, Event};
@@ -6,29 +6,66 @@ use iced::font::{self, Font};
 use iced::time::{every as iced_time_every};
 use iced::theme::{self, Theme};
 use iced::widget::{
-    Column, PickList, button, column, container, horizontal_rule, row, text, text_input, tooltip,
+    Column, ComboBox, PickList, button, checkbox, column, combo_box, container, horizontal_rule, row, scrollable, text,
+    text_input, tooltip,
 };
 use iced::window::icon;
 use iced::widget::tooltip::{Position as TooltipPosition};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration};
 use log::{error, info};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
 use tokio_util::sync::{CancellationToken};
 
 use crate::config::io::{ConfigIO};
-use crate::config::types::{BREATH_DIRECTIONS, BreathDirection, Config, HotkeyConfig};
-use crate::device::connection::connect_device_subscription;
+use crate::config::types::{ACCELERATION_CURVES, BREATH_DIRECTIONS, GAMEPAD_LAYOUTS, HOTKEY_MODES, MAX_CHORD_LENGTH, MATCHING_POLICIES, MAX_GESTURE_LENGTH, MAX_GESTURES, SELECTION_STRATEGIES, SMOOTHINGS, BreathDirection, Config, GestureConfig, HotkeyAction, HotkeyConfig, HotkeyMode, MacroStep};
+use crate::device::calibration::Calibration;
+use crate::device::constants::BREATH_RANGE;
+use crate::device::source::{BreathSource, breath_source_subscription};
 use crate::device::types::{DeviceEvent, DeviceState};
 use crate::error::AppRunError;
 use crate::gui::executor::MyExecutor;
 use crate::gui::open::open_link;
 use crate::gui::style::{TextButtonStyleSheet};
-use crate::gui::types::{Message, HotkeyChange, HotkeyModifier};
+use crate::gui::types::{HOTKEY_ACTION_KINDS, Message, ChordChange, GestureChange, HotkeyActionKind, HotkeyChange};
 use crate::resources::{MUI_SYMBOLS_OUTLINED_BYTES, MUI_SYMBOLS_OUTLINED_FAMILY};
-use crate::sim::breath_input_sim::breath_input_sim;
-use crate::sim::types::{BreathInputSimCommand, Button as InputSimButton, BUTTONS as INPUT_SIM_BUTTONS};
+use crate::sim::backend::InputBackend;
+use crate::sim::breath_input_sim::{breath_input_sim, breath_input_sim_subscription};
+use crate::sim::pointer::PointerDirection;
+use crate::sim::safety::release_pressed_buttons;
+use crate::sim::types::{BreathInputSimCommand, BreathInputSimEvent, Button as InputSimButton, GamepadButton, HeldButtons, BUTTONS as INPUT_SIM_BUTTONS, GAMEPAD_BUTTONS};
 
 const MUI_SYMBOLS_OUTLINED_FONT: Font = Font::with_name(MUI_SYMBOLS_OUTLINED_FAMILY);
 
+// milliseconds
+const MAX_HOTKEY_DELAY: u32 = 10000;
+const MAX_HOTKEY_DURATION: u32 = 60000;
+// taps per second
+const MAX_REPEAT_RATE: u32 = 50;
+// notches per second
+const MAX_SCROLL_SPEED: u32 = 200;
+// pixels per tick
+const MAX_POINTER_SPEED: u32 = 500;
+// seconds
+const MAX_HOLD_TIME: u32 = 3600;
+// samples
+const MAX_SMOOTHING_WINDOW: u32 = 50;
+// milliseconds that closing waits for breath_input_sim to release its buttons
+const CLOSE_TIMEOUT: u64 = 1000;
+// percentage
+const MAX_BREATH_SENSITIVITY: u32 = 400;
+
+fn breath_value_to_string(breath_value: i8) -> String {
+    if breath_value < 0 {
+        format!("{}% sip", -breath_value)
+    }
+    else {
+        format!("{}% puff", breath_value)
+    }
+}
+
 #[cfg(target_os = "macos")]
 fn check_accessibility_access() -> bool {
     return crate::os::macos::check_accessibility_access(true);
@@ -37,13 +74,170 @@ fn check_accessibility_access() -> bool {
 #[cfg(not(target_os = "macos"))]
 fn check_accessibility_access() -> bool{ true }
 
+fn optional_to_string<T: ToString>(value: Option<T>) -> String {
+    match value {
+        None => "".to_string(),
+        Some(value) => value.to_string(),
+    }
+}
+
+// An empty input clears the value. If the input can not be parsed, the current value is kept.
+fn parse_optional_input<T: FromStr>(input: &str, current: Option<T>) -> Option<T> {
+    if input.is_empty() {
+        return None;
+    }
+
+    match input.parse::<T>() {
+        Ok(value) => Some(value),
+        Err(_) => current,
+    }
+}
+
+fn setting_input<'a>(
+    label: &'static str,
+    value: String,
+    unit: &'static str,
+    description: &'static str,
+    on_input: impl Fn(String) -> Message + 'a,
+) -> Element<'a, Message> {
+    tooltip(
+        row![
+            text(label),
+            text_input("", value.as_str())
+                .width(50)
+                .on_input(on_input),
+            text(unit),
+        ].align_items(Alignment::Center).spacing(5),
+        description,
+        TooltipPosition::Bottom,
+    ).into()
+}
+
+/**
+ * Modifiers that are added in front of a chord, the first one that is not part of the chord yet is used.
+ */
+const CHORD_MODIFIERS: [InputSimButton; 4] = [
+    InputSimButton::ControlLeft,
+    InputSimButton::ShiftLeft,
+    InputSimButton::Alt,
+    InputSimButton::MetaLeft,
+];
+
+// Returns true if the chord has changed
+fn apply_chord_change(buttons: &mut Vec<InputSimButton>, change: ChordChange, search_input: &mut String) -> bool {
+    match change {
+        ChordChange::ButtonChange(position, button) => {
+            search_input.clear();
+            if let Some(current) = buttons.get_mut(position) {
+                *current = button;
+            }
+        },
+        ChordChange::Search(input) => {
+            *search_input = input;
+            return false;
+        },
+        ChordChange::SearchClosed(position) => {
+            // buttons that are not in the list, such as KeyCode(123), are chosen by typing them and
+            // leaving the field
+            let input = std::mem::take(search_input);
+            match (buttons.get_mut(position), InputSimButton::from_name(input.trim())) {
+                (Some(current), Some(button)) => *current = button,
+                _ => return false,
+            }
+        },
+        ChordChange::Prepend => {
+            if buttons.len() < MAX_CHORD_LENGTH {
+                let modifier = CHORD_MODIFIERS
+                    .into_iter()
+                    .find(|modifier| !buttons.contains(modifier))
+                    .unwrap_or(InputSimButton::ControlRight);
+                buttons.insert(0, modifier);
+            }
+        },
+        ChordChange::RemoveFirst => {
+            // a chord always has at least one button
+            if buttons.len() > 1 {
+                buttons.remove(0);
+            }
+        },
+    }
+
+    true
+}
+
+// Give every button of every chord its own search state, so that text typed in one field does not
+// show up in the others
+fn resize_button_searches(
+    searches: &mut Vec<Vec<combo_box::State<InputSimButton>>>,
+    chord_lengths: impl ExactSizeIterator<Item = usize>,
+) {
+    searches.resize_with(chord_lengths.len(), Vec::new);
+    for (chord, length) in searches.iter_mut().zip(chord_lengths) {
+        chord.resize_with(length, || combo_box::State::new(INPUT_SIM_BUTTONS.to_vec()));
+    }
+}
+
+// A searchable list for every button of the chord, in order, with buttons to add and remove modifiers
+fn chord_form<'a>(
+    buttons: &[InputSimButton],
+    button_searches: &'a [combo_box::State<InputSimButton>],
+    on_change: impl Fn(ChordChange) -> Message + Copy + 'static,
+) -> Element<'a, Message> {
+    let mut form = row![].align_items(Alignment::Center).spacing(2);
+
+    let mut add_button = button(text("\u{e145}").font(MUI_SYMBOLS_OUTLINED_FONT))
+        .style(theme::Button::Secondary);
+    if buttons.len() < MAX_CHORD_LENGTH {
+        add_button = add_button.on_press(on_change(ChordChange::Prepend));
+    }
+    form = form.push(tooltip(add_button, "Add a modifier in front", TooltipPosition::Bottom));
+
+    if buttons.len() > 1 {
+        form = form.push(tooltip(
+            button(text("\u{e15b}").font(MUI_SYMBOLS_OUTLINED_FONT))
+                .style(theme::Button::Secondary)
+                .on_press(on_change(ChordChange::RemoveFirst)),
+            "Remove the first button",
+            TooltipPosition::Bottom,
+        ));
+    }
+
+    for (position, current) in buttons.iter().enumerate() {
+        if position > 0 {
+            form = form.push(text("+"));
+        }
+
+        let width = if buttons.len() > 2 { 120 } else { 160 };
+        let on_select = move |value| on_change(ChordChange::ButtonChange(position, value));
+
+        form = match button_searches.get(position) {
+            Some(button_search) => form.push(tooltip(
+                ComboBox::new(button_search, "Search…", Some(current), on_select)
+                    .on_input(move |value| on_change(ChordChange::Search(value)))
+                    .on_close(on_change(ChordChange::SearchClosed(position)))
+                    .width(width),
+                "Type to search. Other keys can be entered by typing KeyCode(123), MouseButton(8) or \
+                Char(é) and leaving the field",
+                TooltipPosition::Bottom,
+            )),
+            None => form.push(PickList::new(INPUT_SIM_BUTTONS, Some(*current), on_select).width(width)),
+        };
+    }
+
+    form.into()
+}
+
 pub struct ApplicationFlags {
     config_io: ConfigIO,
+    breath_source: Arc<dyn BreathSource>,
+    input_backend: Arc<dyn InputBackend>,
 }
 
 pub struct MyApplication {
     // this token is cancelled upon exit
     app_cancel: CancellationToken,
+    // taken when closing, to wait for the buttons to be released
+    breath_input_sim_handle: Option<JoinHandle<()>>,
 
     // messages that the user must click away
     notices: Vec<String>,
@@ -52,20 +246,59 @@ pub struct MyApplication {
     config_io: ConfigIO,
     config: Config,
     config_dirty: bool,
+    // index of the hotkey for which the additional settings are visible
+    expanded_hotkey: Option<usize>,
+    // the macro of the expanded hotkey as typed by the user, this might not be valid
+    macro_input: String,
+    macro_error: Option<String>,
+    // the search state of the list of every button of every hotkey and gesture
+    hotkey_button_searches: Vec<Vec<combo_box::State<InputSimButton>>>,
+    gesture_button_searches: Vec<Vec<combo_box::State<InputSimButton>>>,
+    // the text typed in the search field that has focus
+    button_search_input: String,
     // this flag is used to make sure that a user is not spammed with save configuration errors
     displayed_config_save_error: bool,
 
+    // the device (or simulation) that breath values are read from
+    breath_source: Arc<dyn BreathSource>,
+    // the neutral breath value of each device, shared with the task that reads the device
+    calibration: Arc<Calibration>,
+
     // Send events to this futures channel to simulate keyboard/mouse
     breath_input_sim_sender: (Sender<DeviceEvent>, Sender<BreathInputSimCommand>),
+    // Receives status events from breath_input_sim, taken by the subscription
+    breath_input_sim_receiver: Arc<Mutex<Option<Receiver<BreathInputSimEvent>>>>,
 
     // latest state from the device
     latest_device_state: DeviceState,
     latest_breath_value: i8,
+
+    // latest state from breath_input_sim
+    filtered_breath_value: i8,
+    rejected_artifacts: u32,
+    latched_buttons: HeldButtons,
+    morse_code: String,
+    pointer_direction: Option<PointerDirection>,
+    // true if input is only recorded, instead of simulated
+    dry_run: bool,
 }
 
 impl MyApplication {
-    fn before_close(&mut self) {
+    // Cancel all tasks, and close the window once input_sim_task has released its buttons
+    fn before_close(&mut self, id: window::Id) -> Command<Message> {
         self.app_cancel.cancel();
+        let handle = self.breath_input_sim_handle.take();
+
+        Command::perform(
+            async move {
+                if let Some(handle) = handle {
+                    if timeout(Duration::from_millis(CLOSE_TIMEOUT), handle).await.is_err() {
+                        error!("breath_input_sim did not stop in time");
+                    }
+                }
+            },
+            move |_| Message::InputSimStopped(id),
+        )
     }
 
     fn load_symbols_font(&self) -> Command<Message> {
@@ -119,6 +352,11 @@ impl MyApplication {
         return Command::perform(fut, Message::ConfigSaveComplete);
     }
 
+    fn resize_button_searches(&mut self) {
+        resize_button_searches(&mut self.hotkey_button_searches, self.config.hotkeys.iter().map(|hotkey| hotkey.buttons.len()));
+        resize_button_searches(&mut self.gesture_button_searches, self.config.gestures.iter().map(|gesture| gesture.buttons.len()));
+    }
+
     fn send_config(&self) -> Command<Message> {
         let mut sender = self.breath_input_sim_sender.1.clone();
         let config = self.config.clone();
@@ -154,13 +392,13 @@ impl Application for MyApplication {
 
     fn new(flags: ApplicationFlags) -> (MyApplication, Command<Self::Message>) {
         let app_cancel = CancellationToken::new();
-        // todo: wait for the join handle of breath_input_sim when closing
         // todo: wait for device connection to be closed when closing
-        let (bis_event_sender, bis_command_sender, _) = breath_input_sim(app_cancel.clone());
+        let (bis_event_sender, bis_command_sender, bis_receiver, bis_handle) = breath_input_sim(app_cancel.clone(), flags.input_backend.clone());
 
         let mut notices: Vec<String> = Vec::new();
+        let dry_run = flags.input_backend.is_dry_run();
 
-        if !check_accessibility_access() {
+        if !dry_run && !check_accessibility_access() {
             notices.push(
                 "This application translates human breath input to mouse and keyboard hotkeys. \
 To send mouse and keyboard hotkeys on macOS, \"accessibility\" access is required.
@@ -178,14 +416,30 @@ restart the application, and repeat all the steps.".to_string()
 
         let app = MyApplication {
             app_cancel,
+            breath_input_sim_handle: Some(bis_handle),
             notices,
             config_io: flags.config_io,
             config: Config::default(),
             config_dirty: false,
+            expanded_hotkey: None,
+            macro_input: String::new(),
+            macro_error: None,
+            hotkey_button_searches: Vec::new(),
+            gesture_button_searches: Vec::new(),
+            button_search_input: String::new(),
             displayed_config_save_error: false,
+            breath_source: flags.breath_source,
+            calibration: Arc::new(Calibration::default()),
             breath_input_sim_sender: (bis_event_sender, bis_command_sender),
+            breath_input_sim_receiver: Arc::new(Mutex::new(Some(bis_receiver))),
             latest_device_state: DeviceState::Initial,
             latest_breath_value: 0,
+            filtered_breath_value: 0,
+            rejected_artifacts: 0,
+            latched_buttons: HeldButtons::new(),
+            morse_code: String::new(),
+            pointer_direction: None,
+            dry_run,
         };
 
         let command = Command::batch(vec![
@@ -208,19 +462,35 @@ restart the application, and repeat all the steps.".to_string()
             Message::ConfigLoadComplete((config, error_message)) => {
                 info!("Config load complete");
                 self.config = config;
+                self.calibration.set_config(&self.config.calibration);
+                self.resize_button_searches();
                 if let Some(error_message) = error_message {
                     self.notices.push(error_message);
                 }
             },
             Message::ApplyDirtyConfig => {
+                // calibration changes the offsets outside of the GUI. the offsets only need to be
+                // saved, breath_input_sim does not use them
+                let offsets_changed = match self.calibration.take_changed_offsets() {
+                    Some(offsets) => {
+                        self.config.calibration.offsets = offsets;
+                        true
+                    },
+                    None => false,
+                };
+
                 if self.config_dirty {
                     self.config_dirty = false;
+                    self.calibration.set_config(&self.config.calibration);
 
                     return Command::batch(vec![
                         self.send_config(),
                         self.save_config(),
                     ]);
                 }
+                else if offsets_changed {
+                    return self.save_config();
+                }
             },
             Message::ConfigSaveComplete(error_message) => {
                 if !self.displayed_config_save_error {
@@ -240,27 +510,93 @@ restart the application, and repeat all the steps.".to_string()
             },
             Message::EventOccurred(Event::Window(id, window::Event::CloseRequested)) => {
                 info!("Close requested");
-                self.before_close();
+                return self.before_close(id);
+            },
+            Message::InputSimStopped(id) => {
+                // input_sim_task has released its buttons, unless it did not stop in time
+                release_pressed_buttons();
                 return window::close(id);
             },
             Message::DeviceEvent(DeviceEvent::StateChange(state)) => {
                 self.latest_device_state = state;
                 self.latest_breath_value = 0;
+                self.filtered_breath_value = 0;
             },
             Message::DeviceEvent(DeviceEvent::Breath(breath_value)) => {
                 self.latest_breath_value = breath_value;
             },
+            Message::BreathInputSimEvent(BreathInputSimEvent::FilteredBreath(breath_value)) => {
+                self.filtered_breath_value = breath_value;
+            },
+            Message::BreathInputSimEvent(BreathInputSimEvent::RejectedArtifacts(count)) => {
+                self.rejected_artifacts = count;
+            },
+            Message::BreathInputSimEvent(BreathInputSimEvent::LatchedButtons(buttons)) => {
+                self.latched_buttons = buttons;
+            },
+            Message::BreathInputSimEvent(BreathInputSimEvent::MorseCode(code)) => {
+                self.morse_code = code;
+            },
+            Message::BreathInputSimEvent(BreathInputSimEvent::PointerDirection(direction)) => {
+                self.pointer_direction = Some(direction);
+            },
+            Message::BreathInputSimEvent(BreathInputSimEvent::HoldTimeExceeded(hotkey, max_hold_time)) => {
+                // 1-based, as the user would count the hotkeys
+                let hotkey_name = match self.config.hotkeys.iter().position(|config| *config == hotkey) {
+                    Some(index) => format!("Hotkey {}", index + 1),
+                    None => "A hotkey".to_string(),
+                };
+
+                if hotkey.mode == HotkeyMode::Latch {
+                    self.notices.push(format!(
+                        "{} has been unlatched because it was latched for longer than {} seconds.",
+                        hotkey_name,
+                        max_hold_time.as_secs(),
+                    ));
+                }
+                else {
+                    self.notices.push(format!(
+                        "{} has been released because it was held for longer than {} seconds.\n\n\
+                        It will not be pressed again until you stop {}.",
+                        hotkey_name,
+                        max_hold_time.as_secs(),
+                        match hotkey.breath_direction {
+                            BreathDirection::Puff => "puffing",
+                            BreathDirection::Sip => "sipping",
+                        },
+                    ));
+                }
+            },
+            Message::BreathInputSimEvent(BreathInputSimEvent::GamepadError(message)) => {
+                // breath_input_sim has left the gamepad disabled, enabling it again tries again
+                self.config.gamepad.enabled = false;
+                self.config_dirty = true;
+                self.notices.push(format!(
+                    "The virtual gamepad could not be created: {}\n\n\
+                    On Linux, this requires write access to /dev/uinput.",
+                    message,
+                ));
+            },
 
             Message::AddHotkey => {
                 self.config.hotkeys.push(HotkeyConfig {
                     breath_direction: BreathDirection::Puff,
                     threshold: None,
-                    modifier_shift: false,
-                    modifier_ctrl: false,
-                    modifier_meta: false,
-                    modifier_alt: false,
-                    button: InputSimButton::MouseLeft,
+                    upper_threshold: None,
+                    release_threshold: None,
+                    activation_delay: None,
+                    release_delay: None,
+                    mode: HotkeyMode::Hold,
+                    tap_duration: None,
+                    min_repeat_rate: None,
+                    max_repeat_rate: None,
+                    min_duration: None,
+                    max_duration: None,
+                    max_hold_time: None,
+                    buttons: vec![InputSimButton::MouseLeft],
+                    action: HotkeyAction::Button,
                 });
+                self.resize_button_searches();
                 self.config_dirty = true;
             },
             Message::HotkeyChange(index, change) => {
@@ -271,8 +607,10 @@ restart the application, and repeat all the steps.".to_string()
                         HotkeyChange::BreathDirectionChange(direction) => {
                             config.breath_direction = direction;
                         },
-                        HotkeyChange::ButtonChange(button) => {
-                            config.button = button;
+                        HotkeyChange::ChordChange(change) => {
+                            if !apply_chord_change(&mut config.buttons, change, &mut self.button_search_input) {
+                                return Command::none();
+                            }
                         },
                         HotkeyChange::ThresholdChange(threshold_str) => {
                             if threshold_str.is_empty() {
@@ -285,26 +623,324 @@ restart the application, and repeat all the steps.".to_string()
                                 // ignore parse error, in which case the value is not changed
                             }
                         },
-                        HotkeyChange::ModifierToggle(HotkeyModifier::Shift) => {
-                            config.modifier_shift = !config.modifier_shift;
+                        HotkeyChange::UpperThresholdChange(threshold_str) => {
+                            config.upper_threshold = parse_optional_input(&threshold_str, config.upper_threshold)
+                                .map(|threshold: i8| threshold.clamp(2, 100));
+                        },
+                        HotkeyChange::ReleaseThresholdChange(threshold_str) => {
+                            if threshold_str.is_empty() {
+                                config.release_threshold = None;
+                            }
+                            else {
+                                if let Ok(strength) = threshold_str.parse::<i8>() {
+                                    config.release_threshold = Some(strength.clamp(1, 99));
+                                }
+                                // ignore parse error, in which case the value is not changed
+                            }
+                        },
+                        HotkeyChange::ActivationDelayChange(delay_str) => {
+                            config.activation_delay = parse_optional_input(&delay_str, config.activation_delay)
+                                .map(|delay: u32| delay.min(MAX_HOTKEY_DELAY));
+                        },
+                        HotkeyChange::ReleaseDelayChange(delay_str) => {
+                            config.release_delay = parse_optional_input(&delay_str, config.release_delay)
+                                .map(|delay: u32| delay.min(MAX_HOTKEY_DELAY));
+                        },
+                        HotkeyChange::MinDurationChange(duration_str) => {
+                            config.min_duration = parse_optional_input(&duration_str, config.min_duration)
+                                .map(|duration: u32| duration.min(MAX_HOTKEY_DURATION));
+                        },
+                        HotkeyChange::MaxDurationChange(duration_str) => {
+                            config.max_duration = parse_optional_input(&duration_str, config.max_duration)
+                                .map(|duration: u32| duration.clamp(1, MAX_HOTKEY_DURATION));
+                        },
+                        HotkeyChange::MaxHoldTimeChange(seconds_str) => {
+                            config.max_hold_time = parse_optional_input(&seconds_str, config.max_hold_time)
+                                .map(|seconds: u32| seconds.clamp(1, MAX_HOLD_TIME));
+                        },
+                        HotkeyChange::ActionChange(kind) => {
+                            if kind != HotkeyActionKind::of(&config.action) {
+                                config.action = match kind {
+                                    HotkeyActionKind::Button => HotkeyAction::Button,
+                                    HotkeyActionKind::Macro => HotkeyAction::Macro(Vec::new()),
+                                    HotkeyActionKind::Scroll => HotkeyAction::Scroll { horizontal: false, speed: None },
+                                    HotkeyActionKind::GamepadButton => HotkeyAction::GamepadButton(GamepadButton::South),
+                                };
+                                self.macro_input.clear();
+                                self.macro_error = None;
+                            }
+                        },
+                        HotkeyChange::MacroChange(macro_str) => {
+                            match MacroStep::parse_list(&macro_str) {
+                                Ok(steps) => {
+                                    config.action = HotkeyAction::Macro(steps);
+                                    self.macro_error = None;
+                                },
+                                Err(err) => {
+                                    // keep the previous macro until the input is valid
+                                    self.macro_error = Some(err.to_string());
+                                },
+                            }
+                            self.macro_input = macro_str;
+                        },
+                        HotkeyChange::ScrollHorizontalToggle(value) => {
+                            if let HotkeyAction::Scroll { horizontal, .. } = &mut config.action {
+                                *horizontal = value;
+                            }
+                        },
+                        HotkeyChange::ScrollSpeedChange(speed_str) => {
+                            if let HotkeyAction::Scroll { speed, .. } = &mut config.action {
+                                *speed = parse_optional_input(&speed_str, *speed)
+                                    .map(|speed: u32| speed.clamp(1, MAX_SCROLL_SPEED));
+                            }
+                        },
+                        HotkeyChange::GamepadButtonChange(button) => {
+                            config.action = HotkeyAction::GamepadButton(button);
                         },
-                        HotkeyChange::ModifierToggle(HotkeyModifier::Ctrl) => {
-                            config.modifier_ctrl = !config.modifier_ctrl;
+                        HotkeyChange::ModeChange(mode) => {
+                            config.mode = mode;
                         },
-                        HotkeyChange::ModifierToggle(HotkeyModifier::Meta) => {
-                            config.modifier_meta = !config.modifier_meta;
+                        HotkeyChange::TapDurationChange(duration_str) => {
+                            config.tap_duration = parse_optional_input(&duration_str, config.tap_duration)
+                                .map(|duration: u32| duration.clamp(1, MAX_HOTKEY_DELAY));
                         },
-                        HotkeyChange::ModifierToggle(HotkeyModifier::Alt) => {
-                            config.modifier_alt = !config.modifier_alt;
+                        HotkeyChange::MinRepeatRateChange(rate_str) => {
+                            config.min_repeat_rate = parse_optional_input(&rate_str, config.min_repeat_rate)
+                                .map(|rate: u32| rate.clamp(1, MAX_REPEAT_RATE));
+                        },
+                        HotkeyChange::MaxRepeatRateChange(rate_str) => {
+                            config.max_repeat_rate = parse_optional_input(&rate_str, config.max_repeat_rate)
+                                .map(|rate: u32| rate.clamp(1, MAX_REPEAT_RATE));
                         },
                         HotkeyChange::Delete => {
                             self.config.hotkeys.remove(index);
+                            if index < self.hotkey_button_searches.len() {
+                                self.hotkey_button_searches.remove(index);
+                            }
+                            self.expanded_hotkey = None;
                         },
                     }
 
+                    self.resize_button_searches();
                     self.config_dirty = true;
                 }
             },
+            Message::ToggleHotkeySettings(index) => {
+                if self.expanded_hotkey == Some(index) {
+                    self.expanded_hotkey = None;
+                }
+                else {
+                    self.expanded_hotkey = Some(index);
+                    self.macro_input = match self.config.hotkeys.get(index).map(|config| &config.action) {
+                        Some(HotkeyAction::Macro(steps)) => MacroStep::format_list(steps),
+                        _ => String::new(),
+                    };
+                    self.macro_error = None;
+                }
+            },
+            Message::AddGesture => {
+                self.config.gestures.push(GestureConfig {
+                    breaths: vec![BreathDirection::Puff, BreathDirection::Puff],
+                    buttons: vec![InputSimButton::MouseLeft],
+                });
+                self.resize_button_searches();
+                self.config_dirty = true;
+            },
+            Message::GestureChange(index, change) => {
+                if index < self.config.gestures.len() {
+                    let gesture = &mut self.config.gestures[index];
+
+                    match change {
+                        GestureChange::AppendBreath(direction) => {
+                            if gesture.breaths.len() < MAX_GESTURE_LENGTH {
+                                gesture.breaths.push(direction);
+                            }
+                        },
+                        GestureChange::RemoveBreath => {
+                            gesture.breaths.pop();
+                        },
+                        GestureChange::ChordChange(change) => {
+                            if !apply_chord_change(&mut gesture.buttons, change, &mut self.button_search_input) {
+                                return Command::none();
+                            }
+                        },
+                        GestureChange::Delete => {
+                            self.config.gestures.remove(index);
+                            if index < self.gesture_button_searches.len() {
+                                self.gesture_button_searches.remove(index);
+                            }
+                        },
+                    }
+
+                    self.resize_button_searches();
+                    self.config_dirty = true;
+                }
+            },
+            Message::PuffSelectionChange(strategy) => {
+                self.config.puff_selection = strategy;
+                self.config_dirty = true;
+            },
+            Message::SipSelectionChange(strategy) => {
+                self.config.sip_selection = strategy;
+                self.config_dirty = true;
+            },
+            Message::PuffMatchingChange(policy) => {
+                self.config.puff_matching = policy;
+                self.config_dirty = true;
+            },
+            Message::SipMatchingChange(policy) => {
+                self.config.sip_matching = policy;
+                self.config_dirty = true;
+            },
+            Message::PeakCommitDelayChange(delay_str) => {
+                self.config.peak_commit_delay = parse_optional_input(&delay_str, self.config.peak_commit_delay)
+                    .map(|delay: u32| delay.clamp(1, MAX_HOTKEY_DELAY));
+                self.config_dirty = true;
+            },
+            Message::SmoothingChange(smoothing) => {
+                self.config.filter.smoothing = smoothing;
+                self.config_dirty = true;
+            },
+            Message::SmoothingWindowChange(window_str) => {
+                self.config.filter.smoothing_window = parse_optional_input(&window_str, self.config.filter.smoothing_window)
+                    .map(|window: u32| window.clamp(1, MAX_SMOOTHING_WINDOW));
+                self.config_dirty = true;
+            },
+            Message::SpikeFilterToggle(enabled) => {
+                self.config.filter.spike_filter = enabled;
+                self.config_dirty = true;
+            },
+            Message::BreathDeadzoneChange(deadzone_str) => {
+                self.config.filter.deadzone = parse_optional_input(&deadzone_str, self.config.filter.deadzone)
+                    .map(|deadzone: i8| deadzone.clamp(0, 99));
+                self.config_dirty = true;
+            },
+            Message::BreathCurveChange(direction, curve) => {
+                match direction {
+                    BreathDirection::Puff => self.config.filter.puff_curve = curve,
+                    BreathDirection::Sip => self.config.filter.sip_curve = curve,
+                }
+                self.config_dirty = true;
+            },
+            Message::BreathSensitivityChange(direction, sensitivity_str) => {
+                let sensitivity = match direction {
+                    BreathDirection::Puff => &mut self.config.filter.puff_sensitivity,
+                    BreathDirection::Sip => &mut self.config.filter.sip_sensitivity,
+                };
+                *sensitivity = parse_optional_input(&sensitivity_str, *sensitivity)
+                    .map(|sensitivity: u32| sensitivity.clamp(1, MAX_BREATH_SENSITIVITY));
+                self.config_dirty = true;
+            },
+            Message::CalibrationZero => {
+                self.calibration.zero();
+            },
+            Message::CalibrationReset => {
+                self.calibration.reset();
+            },
+            Message::AutoZeroToggle(enabled) => {
+                self.config.calibration.auto_zero = enabled;
+                self.config_dirty = true;
+            },
+            Message::ArtifactToggle(enabled) => {
+                self.config.artifacts.enabled = enabled;
+                self.config_dirty = true;
+            },
+            Message::ArtifactSteepnessChange(steepness_str) => {
+                self.config.artifacts.steepness = parse_optional_input(&steepness_str, self.config.artifacts.steepness)
+                    .map(|steepness: i8| steepness.clamp(1, 100));
+                self.config_dirty = true;
+            },
+            Message::ArtifactMaxDurationChange(duration_str) => {
+                self.config.artifacts.max_duration = parse_optional_input(&duration_str, self.config.artifacts.max_duration)
+                    .map(|duration: u32| duration.clamp(1, MAX_HOTKEY_DELAY));
+                self.config_dirty = true;
+            },
+            Message::GestureThresholdChange(threshold_str) => {
+                self.config.gesture_threshold = parse_optional_input(&threshold_str, self.config.gesture_threshold)
+                    .map(|threshold: i8| threshold.clamp(1, 99));
+                self.config_dirty = true;
+            },
+            Message::GestureWindowChange(window_str) => {
+                self.config.gesture_window = parse_optional_input(&window_str, self.config.gesture_window)
+                    .map(|window: u32| window.clamp(1, MAX_HOTKEY_DELAY));
+                self.config_dirty = true;
+            },
+            Message::MorseToggle(enabled) => {
+                self.config.morse.enabled = enabled;
+                if enabled {
+                    self.config.pointer.enabled = false;
+                }
+                self.config_dirty = true;
+            },
+            Message::MorseThresholdChange(threshold_str) => {
+                self.config.morse.threshold = parse_optional_input(&threshold_str, self.config.morse.threshold)
+                    .map(|threshold: i8| threshold.clamp(1, 99));
+                self.config_dirty = true;
+            },
+            Message::MorseDashDurationChange(duration_str) => {
+                self.config.morse.dash_duration = parse_optional_input(&duration_str, self.config.morse.dash_duration)
+                    .map(|duration: u32| duration.clamp(1, MAX_HOTKEY_DELAY));
+                self.config_dirty = true;
+            },
+            Message::MorseCharacterGapChange(gap_str) => {
+                self.config.morse.character_gap = parse_optional_input(&gap_str, self.config.morse.character_gap)
+                    .map(|gap: u32| gap.clamp(1, MAX_HOTKEY_DELAY));
+                self.config_dirty = true;
+            },
+            Message::PointerToggle(enabled) => {
+                self.config.pointer.enabled = enabled;
+                if enabled {
+                    self.config.morse.enabled = false;
+                }
+                self.config_dirty = true;
+            },
+            Message::PointerThresholdChange(threshold_str) => {
+                self.config.pointer.threshold = parse_optional_input(&threshold_str, self.config.pointer.threshold)
+                    .map(|threshold: i8| threshold.clamp(1, 99));
+                self.config_dirty = true;
+            },
+            Message::PointerMaxSpeedChange(speed_str) => {
+                self.config.pointer.max_speed = parse_optional_input(&speed_str, self.config.pointer.max_speed)
+                    .map(|speed: u32| speed.clamp(1, MAX_POINTER_SPEED));
+                self.config_dirty = true;
+            },
+            Message::PointerAccelerationChange(acceleration) => {
+                self.config.pointer.acceleration = acceleration;
+                self.config_dirty = true;
+            },
+            Message::PointerRotationIntervalChange(interval_str) => {
+                self.config.pointer.rotation_interval = parse_optional_input(&interval_str, self.config.pointer.rotation_interval)
+                    .map(|interval: u32| interval.clamp(1, MAX_HOTKEY_DELAY));
+                self.config_dirty = true;
+            },
+            Message::GamepadToggle(enabled) => {
+                self.config.gamepad.enabled = enabled;
+                self.config_dirty = true;
+            },
+            Message::GamepadLayoutChange(layout) => {
+                self.config.gamepad.layout = layout;
+                self.config_dirty = true;
+            },
+            Message::GamepadDeadzoneChange(deadzone_str) => {
+                self.config.gamepad.deadzone = parse_optional_input(&deadzone_str, self.config.gamepad.deadzone)
+                    .map(|deadzone: i8| deadzone.clamp(0, 99));
+                self.config_dirty = true;
+            },
+            Message::GamepadResponseCurveChange(curve) => {
+                self.config.gamepad.response_curve = curve;
+                self.config_dirty = true;
+            },
+            Message::MaxHoldTimeChange(max_hold_time_str) => {
+                if max_hold_time_str.is_empty() {
+                    self.config.max_hold_time = None;
+                    self.config_dirty = true;
+                }
+                else if let Ok(seconds) = max_hold_time_str.parse::<u32>() {
+                    self.config.max_hold_time = Some(seconds.clamp(1, MAX_HOLD_TIME));
+                    self.config_dirty = true;
+                }
+                // ignore parse error, in which case the value is not changed
+            },
 
             _ => {}
         }
@@ -316,10 +952,15 @@ restart the application, and repeat all the steps.".to_string()
         Subscription::batch([
             event::listen().map(Message::EventOccurred),
             iced_time_every(Duration::from_secs(1)).map(|_| Message::ApplyDirtyConfig),
-            connect_device_subscription(
+            breath_source_subscription(
+                self.breath_source.clone(),
                 self.app_cancel.clone(),
                 vec![self.breath_input_sim_sender.0.clone()],
-            ).map(Message::DeviceEvent)
+                self.calibration.clone(),
+            ).map(Message::DeviceEvent),
+            breath_input_sim_subscription(
+                self.breath_input_sim_receiver.clone(),
+            ).map(Message::BreathInputSimEvent),
         ])
     }
 
@@ -339,7 +980,7 @@ restart the application, and repeat all the steps.".to_string()
             .into()
         }
 
-        let modifier_toggle = |
+        let icon_toggle = |
             description: &'static str,
             symbol: char,
             checked: bool,
@@ -356,39 +997,183 @@ restart the application, and repeat all the steps.".to_string()
             ).into()
         };
 
+        let hotkey_settings = |index: usize, config: &HotkeyConfig| -> Element<Message> {
+            let action_form: Element<Message> = match config.action {
+                HotkeyAction::Button => column![].into(),
+                HotkeyAction::Macro(_) => column![
+                    tooltip(
+                        text_input("ControlLeft+KeyC, 200ms, \"text\"", self.macro_input.as_str())
+                            .width(400)
+                            .on_input(move |value| Message::HotkeyChange(index, HotkeyChange::MacroChange(value))),
+                        "Steps separated by commas: buttons joined by +, a delay such as 200ms, or text between \
+                        quotes. Within text, type \\\" for a quote and \\\\ for a backslash",
+                        TooltipPosition::Bottom,
+                    ),
+                    text(self.macro_error.as_deref().unwrap_or("")),
+                ].spacing(5).align_items(Alignment::End).into(),
+                HotkeyAction::Scroll { horizontal, speed } => column![
+                    checkbox("Scroll horizontally", horizontal)
+                        .on_toggle(move |value| Message::HotkeyChange(index, HotkeyChange::ScrollHorizontalToggle(value))),
+                    setting_input(
+                        "Maximum scroll speed",
+                        optional_to_string(speed),
+                        "per second",
+                        "How many notches to scroll per second while breathing as hard as possible. \
+                        The speed increases as the breath exceeds the threshold further.",
+                        move |value| Message::HotkeyChange(index, HotkeyChange::ScrollSpeedChange(value)),
+                    ),
+                ].spacing(10).align_items(Alignment::End).into(),
+                HotkeyAction::GamepadButton(gamepad_button) => row![
+                    text("Gamepad button"),
+                    PickList::new(
+                        GAMEPAD_BUTTONS,
+                        Some(gamepad_button),
+                        move |value| Message::HotkeyChange(index, HotkeyChange::GamepadButtonChange(value)),
+                    ).width(200),
+                ].align_items(Alignment::Center).spacing(5).into(),
+            };
+
+            column![
+                row![
+                    text("Action"),
+                    tooltip(
+                        PickList::new(
+                            HOTKEY_ACTION_KINDS,
+                            Some(HotkeyActionKind::of(&config.action)),
+                            move |value| Message::HotkeyChange(index, HotkeyChange::ActionChange(value)),
+                        ).width(160),
+                        "A new breath stops the macro if it is still running. \
+                        Puffs scroll down or right, sips scroll up or left. \
+                        Gamepad buttons are held while the hotkey is pressed, in every mode.",
+                        TooltipPosition::Bottom,
+                    ),
+                ].align_items(Alignment::Center).spacing(5),
+                action_form,
+                row![
+                    text("Mode"),
+                    PickList::new(
+                        HOTKEY_MODES,
+                        Some(config.mode),
+                        move |value| Message::HotkeyChange(index, HotkeyChange::ModeChange(value)),
+                    ).width(160),
+                ].align_items(Alignment::Center).spacing(5),
+                setting_input(
+                    "Tap duration",
+                    optional_to_string(config.tap_duration),
+                    "ms",
+                    "How long the button is pressed in one of the tap modes",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::TapDurationChange(value)),
+                ),
+                setting_input(
+                    "Repeat at least",
+                    optional_to_string(config.min_repeat_rate),
+                    "per second",
+                    "How often the button is tapped in the Repeat mode when the breath is at the threshold",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::MinRepeatRateChange(value)),
+                ),
+                setting_input(
+                    "Repeat at most",
+                    optional_to_string(config.max_repeat_rate),
+                    "per second",
+                    "How often the button is tapped in the Repeat mode when breathing as hard as possible",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::MaxRepeatRateChange(value)),
+                ),
+                setting_input(
+                    "Release below",
+                    optional_to_string(config.release_threshold),
+                    "%",
+                    "Leave empty to release as soon as the breath drops below the threshold",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::ReleaseThresholdChange(value)),
+                ),
+                setting_input(
+                    "Press after",
+                    optional_to_string(config.activation_delay),
+                    "ms",
+                    "How long the breath must remain above the threshold before pressing",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::ActivationDelayChange(value)),
+                ),
+                setting_input(
+                    "Release after",
+                    optional_to_string(config.release_delay),
+                    "ms",
+                    "How long the breath must remain below the threshold before releasing",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::ReleaseDelayChange(value)),
+                ),
+                setting_input(
+                    "Only after breathing for",
+                    optional_to_string(config.min_duration),
+                    "ms",
+                    "The breath must last at least this long before the hotkey is pressed",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::MinDurationChange(value)),
+                ),
+                setting_input(
+                    "Only for breaths shorter than",
+                    optional_to_string(config.max_duration),
+                    "ms",
+                    "If set, the hotkey is tapped when a breath this short ends, unless a hotkey with a \
+                    minimum duration has been pressed during the breath",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::MaxDurationChange(value)),
+                ),
+                setting_input(
+                    "Release if held longer than",
+                    optional_to_string(config.max_hold_time),
+                    "seconds",
+                    "Leave empty to use the maximum hold time for all buttons. Once released, the hotkey \
+                    is not pressed again until the breath returns to neutral. Latched buttons are \
+                    unlatched",
+                    move |value| Message::HotkeyChange(index, HotkeyChange::MaxHoldTimeChange(value)),
+                ),
+            ]
+            .spacing(10)
+            .align_items(Alignment::End)
+            .into()
+        };
+
+        let overlapping_hotkeys = self.config.overlapping_hotkeys();
+
         let hotkey_form = |index: usize, config: &HotkeyConfig| -> Element<Message> {
             let threshold_value = match config.threshold {
                 None => "".to_string(),
                 Some(value) => value.to_string(),
             };
 
-            row![
+            let expanded = self.expanded_hotkey == Some(index);
+
+            // 1-based, as the user would count the hotkeys
+            let overlaps_with: Vec<String> = overlapping_hotkeys
+                .iter()
+                .filter_map(|&(a, b)| {
+                    if a == index { Some(b) } else if b == index { Some(a) } else { None }
+                })
+                .map(|other| (other + 1).to_string())
+                .collect();
+
+            let form = row![
                 PickList::new(
                     BREATH_DIRECTIONS,
                     Some(config.breath_direction),
                     move |value| Message::HotkeyChange(index, HotkeyChange::BreathDirectionChange(value)),
                 ).width(60),
 
-                row![
-                    text("≥"),
-                    text_input("", threshold_value.as_str())
-                        .width(30)
-                        .on_input(move |value| Message::HotkeyChange(index, HotkeyChange::ThresholdChange(value))),
-                    text("%"),
-                ].align_items(Alignment::Center).spacing(2),
-
-                row![
-                    modifier_toggle("Shift", '\u{e5f2}', config.modifier_shift, Message::HotkeyChange(index, HotkeyChange::ModifierToggle(HotkeyModifier::Shift))),
-                    modifier_toggle("Ctrl", '\u{eae6}', config.modifier_ctrl, Message::HotkeyChange(index, HotkeyChange::ModifierToggle(HotkeyModifier::Ctrl))),
-                    modifier_toggle("Meta", '\u{eae7}', config.modifier_meta, Message::HotkeyChange(index, HotkeyChange::ModifierToggle(HotkeyModifier::Meta))),
-                    modifier_toggle("Alt", '\u{eae8}', config.modifier_alt, Message::HotkeyChange(index, HotkeyChange::ModifierToggle(HotkeyModifier::Alt))),
-                ].spacing(2),
-
-                PickList::new(
-                    INPUT_SIM_BUTTONS,
-                    Some(config.button),
-                    move |value| Message::HotkeyChange(index, HotkeyChange::ButtonChange(value)),
-                ).width(200),
+                tooltip(
+                    row![
+                        text("≥"),
+                        text_input("", threshold_value.as_str())
+                            .width(30)
+                            .on_input(move |value| Message::HotkeyChange(index, HotkeyChange::ThresholdChange(value))),
+                        text("<"),
+                        text_input("", optional_to_string(config.upper_threshold).as_str())
+                            .width(30)
+                            .on_input(move |value| Message::HotkeyChange(index, HotkeyChange::UpperThresholdChange(value))),
+                        text("%"),
+                    ].align_items(Alignment::Center).spacing(2),
+                    "Leave the upper threshold empty to match all breaths above the threshold",
+                    TooltipPosition::Bottom,
+                ),
+
+                chord_form(&config.buttons, self.hotkey_button_searches.get(index).map_or(&[], Vec::as_slice), move |change| Message::HotkeyChange(index, HotkeyChange::ChordChange(change))),
+
+                icon_toggle("More settings", '\u{e8b8}', expanded, Message::ToggleHotkeySettings(index)),
 
                 button(
                     text("\u{e92b}").font(MUI_SYMBOLS_OUTLINED_FONT)
@@ -397,8 +1182,19 @@ restart the application, and repeat all the steps.".to_string()
                 .on_press(Message::HotkeyChange(index, HotkeyChange::Delete)),
             ]
             .align_items(Alignment::Center)
-            .spacing(20)
-            .into()
+            .spacing(12);
+
+            let mut form = column![form].spacing(10).align_items(Alignment::Center);
+
+            if !overlaps_with.is_empty() {
+                form = form.push(text(format!("Overlaps with hotkey {}", overlaps_with.join(", "))));
+            }
+
+            if expanded {
+                form = form.push(hotkey_settings(index, config));
+            }
+
+            form.into()
         };
 
         let mut add_hotkey_button = button(
@@ -410,43 +1206,472 @@ restart the application, and repeat all the steps.".to_string()
             add_hotkey_button = add_hotkey_button.on_press(Message::AddHotkey);
         }
 
+        let connected = self.latest_device_state == DeviceState::Connected;
+        let calibration_offset = match self.calibration.offset() {
+            Some(offset) => format!("Neutral is off by {:.1}%", offset / f32::from(BREATH_RANGE) * 100.0),
+            None => "Connect a device to calibrate it".to_string(),
+        };
+
+        let calibration_settings = column![
+            row![
+                text(calibration_offset),
+                tooltip(
+                    button(text("Zero now"))
+                        .style(theme::Button::Secondary)
+                        .on_press_maybe(connected.then_some(Message::CalibrationZero)),
+                    "Use the current breath value of the device as neutral. Do not breathe into the tube while doing this",
+                    TooltipPosition::Bottom,
+                ),
+                button(text("Reset"))
+                    .style(theme::Button::Secondary)
+                    .on_press_maybe(connected.then_some(Message::CalibrationReset)),
+            ].align_items(Alignment::Center).spacing(10),
+            tooltip(
+                checkbox("Adjust neutral automatically", self.config.calibration.auto_zero)
+                    .on_toggle(Message::AutoZeroToggle),
+                "While the breath stays close to neutral for a few seconds, neutral slowly follows it. \
+                This compensates for drift caused by temperature and moisture",
+                TooltipPosition::Bottom,
+            ),
+        ].spacing(10).align_items(Alignment::End);
+
+        let filter_settings = column![
+            tooltip(
+                row![
+                    text("Smoothing"),
+                    PickList::new(
+                        SMOOTHINGS,
+                        Some(self.config.filter.smoothing),
+                        Message::SmoothingChange,
+                    ).width(160),
+                ].align_items(Alignment::Center).spacing(5),
+                "Smoothing reduces sensor noise, but makes the breath value respond more slowly",
+                TooltipPosition::Bottom,
+            ),
+            setting_input(
+                "Smooth over",
+                optional_to_string(self.config.filter.smoothing_window),
+                "samples",
+                "The device is sampled every 10 milliseconds",
+                Message::SmoothingWindowChange,
+            ),
+            tooltip(
+                checkbox("Ignore spikes", self.config.filter.spike_filter)
+                    .on_toggle(Message::SpikeFilterToggle),
+                "A single sample that differs from the samples around it is ignored",
+                TooltipPosition::Bottom,
+            ),
+            setting_input(
+                "Neutral below",
+                optional_to_string(self.config.filter.deadzone),
+                "%",
+                "Breaths weaker than this are treated as no breath at all",
+                Message::BreathDeadzoneChange,
+            ),
+            row![
+                setting_input(
+                    "Puff sensitivity",
+                    optional_to_string(self.config.filter.puff_sensitivity),
+                    "%",
+                    "Puffs are multiplied by this percentage",
+                    |value| Message::BreathSensitivityChange(BreathDirection::Puff, value),
+                ),
+                PickList::new(
+                    ACCELERATION_CURVES,
+                    Some(self.config.filter.puff_curve),
+                    |value| Message::BreathCurveChange(BreathDirection::Puff, value),
+                ).width(160),
+            ].align_items(Alignment::Center).spacing(10),
+            row![
+                setting_input(
+                    "Sip sensitivity",
+                    optional_to_string(self.config.filter.sip_sensitivity),
+                    "%",
+                    "Sips are multiplied by this percentage",
+                    |value| Message::BreathSensitivityChange(BreathDirection::Sip, value),
+                ),
+                PickList::new(
+                    ACCELERATION_CURVES,
+                    Some(self.config.filter.sip_curve),
+                    |value| Message::BreathCurveChange(BreathDirection::Sip, value),
+                ).width(160),
+            ].align_items(Alignment::Center).spacing(10),
+        ].spacing(10).align_items(Alignment::End);
+
+        let artifact_settings = column![
+            tooltip(
+                checkbox("Ignore coughs, sneezes and talking", self.config.artifacts.enabled)
+                    .on_toggle(Message::ArtifactToggle),
+                "Very steep and short breaths are ignored. Steep breaths that are intentional \
+                are delayed until they have lasted long enough.",
+                TooltipPosition::Bottom,
+            ),
+            setting_input(
+                "Steeper than",
+                optional_to_string(self.config.artifacts.steepness),
+                "% per 10 ms",
+                "Lower this to ignore more breaths",
+                Message::ArtifactSteepnessChange,
+            ),
+            setting_input(
+                "Shorter than",
+                optional_to_string(self.config.artifacts.max_duration),
+                "ms",
+                "Steep breaths that last longer than this are intentional",
+                Message::ArtifactMaxDurationChange,
+            ),
+            text(format!("Ignored so far: {}", self.rejected_artifacts)),
+        ].spacing(10).align_items(Alignment::End);
+
+        let selection_settings = column![
+            tooltip(
+                row![
+                    text("Puffs press a hotkey"),
+                    PickList::new(
+                        SELECTION_STRATEGIES,
+                        Some(self.config.puff_selection),
+                        Message::PuffSelectionChange,
+                    ).width(160),
+                ].align_items(Alignment::Center).spacing(5),
+                "Waiting for the peak of the breath avoids pressing the hotkeys with a lower threshold on the way up",
+                TooltipPosition::Bottom,
+            ),
+            tooltip(
+                row![
+                    text("Sips press a hotkey"),
+                    PickList::new(
+                        SELECTION_STRATEGIES,
+                        Some(self.config.sip_selection),
+                        Message::SipSelectionChange,
+                    ).width(160),
+                ].align_items(Alignment::Center).spacing(5),
+                "Waiting for the peak of the breath avoids pressing the hotkeys with a lower threshold on the way up",
+                TooltipPosition::Bottom,
+            ),
+            setting_input(
+                "Peak is reached after",
+                optional_to_string(self.config.peak_commit_delay),
+                "ms",
+                "How long the breath must stop getting stronger before the hotkey of its peak is pressed",
+                Message::PeakCommitDelayChange,
+            ),
+            tooltip(
+                row![
+                    text("Puffs activate"),
+                    PickList::new(
+                        MATCHING_POLICIES,
+                        Some(self.config.puff_matching),
+                        Message::PuffMatchingChange,
+                    ).width(160),
+                ].align_items(Alignment::Center).spacing(5),
+                "With all matches, the buttons of every hotkey whose threshold is reached are held together. With the highest reached, the hotkeys with a lower threshold are not pressed while the breath fades out",
+                TooltipPosition::Bottom,
+            ),
+            tooltip(
+                row![
+                    text("Sips activate"),
+                    PickList::new(
+                        MATCHING_POLICIES,
+                        Some(self.config.sip_matching),
+                        Message::SipMatchingChange,
+                    ).width(160),
+                ].align_items(Alignment::Center).spacing(5),
+                "With all matches, the buttons of every hotkey whose threshold is reached are held together. With the highest reached, the hotkeys with a lower threshold are not pressed while the breath fades out",
+                TooltipPosition::Bottom,
+            ),
+        ].spacing(10).align_items(Alignment::End);
+
+        let gesture_form = |index: usize, gesture: &GestureConfig| -> Element<Message> {
+            let breaths: Vec<String> = gesture.breaths.iter().map(|direction| direction.to_string()).collect();
+            let breaths = if breaths.is_empty() { "…".to_string() } else { breaths.join(", ") };
+
+            column![
+                row![
+                    text(breaths).width(200),
+                    button(text("+ Puff"))
+                        .style(theme::Button::Secondary)
+                        .on_press(Message::GestureChange(index, GestureChange::AppendBreath(BreathDirection::Puff))),
+                    button(text("+ Sip"))
+                        .style(theme::Button::Secondary)
+                        .on_press(Message::GestureChange(index, GestureChange::AppendBreath(BreathDirection::Sip))),
+                    icon_toggle("Remove last breath", '\u{e14a}', false, Message::GestureChange(index, GestureChange::RemoveBreath)),
+                ].align_items(Alignment::Center).spacing(10),
+
+                row![
+                    chord_form(&gesture.buttons, self.gesture_button_searches.get(index).map_or(&[], Vec::as_slice), move |change| Message::GestureChange(index, GestureChange::ChordChange(change))),
+
+                    button(
+                        text("\u{e92b}").font(MUI_SYMBOLS_OUTLINED_FONT)
+                    )
+                    .style(theme::Button::Destructive)
+                    .on_press(Message::GestureChange(index, GestureChange::Delete)),
+                ].align_items(Alignment::Center).spacing(20),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .into()
+        };
+
+        let mut add_gesture_button = button(
+            text("\u{e147}").font(MUI_SYMBOLS_OUTLINED_FONT)
+        )
+        .style(theme::Button::Positive);
+
+        if self.config.gestures.len() < MAX_GESTURES {
+            add_gesture_button = add_gesture_button.on_press(Message::AddGesture);
+        }
+
+        let gesture_settings = column![
+            setting_input(
+                "Gesture breaths above",
+                optional_to_string(self.config.gesture_threshold),
+                "%",
+                "How strong a breath must be to count as part of a gesture",
+                Message::GestureThresholdChange,
+            ),
+            setting_input(
+                "Gesture ends after",
+                optional_to_string(self.config.gesture_window),
+                "ms",
+                "How long to wait for the next breath of a gesture. A breath that might be part of a \
+                gesture only reaches the hotkeys once it lasts this long, or as a single tap if no \
+                gesture matches",
+                Message::GestureWindowChange,
+            ),
+        ].spacing(10).align_items(Alignment::End);
+
+        let morse_settings = column![
+            tooltip(
+                checkbox("Morse code text entry", self.config.morse.enabled)
+                    .on_toggle(Message::MorseToggle),
+                "Short puffs are dots, long puffs are dashes. Sip or pause to end a character. \
+                Hotkeys and gestures are disabled while this is enabled.",
+                TooltipPosition::Bottom,
+            ),
+            setting_input(
+                "Puffs above",
+                optional_to_string(self.config.morse.threshold),
+                "%",
+                "How strong a puff must be to count as a dot or dash",
+                Message::MorseThresholdChange,
+            ),
+            setting_input(
+                "Dash if longer than",
+                optional_to_string(self.config.morse.dash_duration),
+                "ms",
+                "Puffs shorter than this are dots",
+                Message::MorseDashDurationChange,
+            ),
+            setting_input(
+                "Character ends after",
+                optional_to_string(self.config.morse.character_gap),
+                "ms",
+                "How long to wait for the next dot or dash of a character",
+                Message::MorseCharacterGapChange,
+            ),
+        ].spacing(10).align_items(Alignment::End);
+
+        let pointer_settings = column![
+            tooltip(
+                checkbox("Mouse pointer control", self.config.pointer.enabled)
+                    .on_toggle(Message::PointerToggle),
+                "Sip to rotate the direction, puff to move the mouse pointer in that direction. \
+                Hotkeys and gestures are disabled while this is enabled.",
+                TooltipPosition::Bottom,
+            ),
+            setting_input(
+                "Breaths above",
+                optional_to_string(self.config.pointer.threshold),
+                "%",
+                "How strong a breath must be to move the pointer or rotate the direction",
+                Message::PointerThresholdChange,
+            ),
+            setting_input(
+                "Maximum speed",
+                optional_to_string(self.config.pointer.max_speed),
+                "px",
+                "How far the pointer moves every 16 milliseconds while puffing as hard as possible",
+                Message::PointerMaxSpeedChange,
+            ),
+            row![
+                text("Acceleration"),
+                PickList::new(
+                    ACCELERATION_CURVES,
+                    Some(self.config.pointer.acceleration),
+                    Message::PointerAccelerationChange,
+                ).width(160),
+            ].align_items(Alignment::Center).spacing(5),
+            setting_input(
+                "Rotate direction every",
+                optional_to_string(self.config.pointer.rotation_interval),
+                "ms",
+                "How quickly the direction changes while sipping",
+                Message::PointerRotationIntervalChange,
+            ),
+        ].spacing(10).align_items(Alignment::End);
+
+        let gamepad_settings = column![
+            tooltip(
+                checkbox("Virtual gamepad (Linux only)", self.config.gamepad.enabled)
+                    .on_toggle(Message::GamepadToggle),
+                "A virtual gamepad follows the breath strength, for games that support controllers. \
+                Hotkeys can press its buttons.",
+                TooltipPosition::Bottom,
+            ),
+            row![
+                text("Breath moves"),
+                PickList::new(
+                    GAMEPAD_LAYOUTS,
+                    Some(self.config.gamepad.layout),
+                    Message::GamepadLayoutChange,
+                ).width(240),
+            ].align_items(Alignment::Center).spacing(5),
+            setting_input(
+                "Deadzone",
+                optional_to_string(self.config.gamepad.deadzone),
+                "%",
+                "Breaths weaker than this leave the trigger or stick at rest",
+                Message::GamepadDeadzoneChange,
+            ),
+            row![
+                text("Response curve"),
+                PickList::new(
+                    ACCELERATION_CURVES,
+                    Some(self.config.gamepad.response_curve),
+                    Message::GamepadResponseCurveChange,
+                ).width(160),
+            ].align_items(Alignment::Center).spacing(5),
+        ].spacing(10).align_items(Alignment::End);
+
+        let max_hold_time_value = match self.config.max_hold_time {
+            None => "".to_string(),
+            Some(value) => value.to_string(),
+        };
+
+        let max_hold_time_form = tooltip(
+            row![
+                text("Release buttons held longer than"),
+                text_input("", max_hold_time_value.as_str())
+                    .width(40)
+                    .on_input(Message::MaxHoldTimeChange),
+                text("seconds"),
+            ].align_items(Alignment::Center).spacing(5),
+            "Leave empty to hold buttons for as long as needed",
+            TooltipPosition::Bottom,
+        );
+
         let device_state = match self.latest_device_state {
             DeviceState::Initial => "".to_string(),
             DeviceState::Scanning { no_permission: false } => "Scanning…".to_string(),
             DeviceState::Scanning { no_permission: true } => "Not allowed to access Bluetooth!".to_string(),
             DeviceState::Connecting => "Connecting…".to_string(),
-            DeviceState::Connected => {
-                let percentage = self.latest_breath_value;
-                if percentage < 0 {
-                    format!("{}% sip", -percentage)
-                }
-                else {
-                    format!("{}% puff", percentage)
-                }
-            },
+            DeviceState::Connected => format!(
+                "{}  →  filtered {}",
+                breath_value_to_string(self.latest_breath_value),
+                breath_value_to_string(self.filtered_breath_value),
+            ),
+        };
+
+        let sim_state = if self.config.morse.enabled {
+            format!("Morse: {}", self.morse_code)
+        }
+        else if self.config.pointer.enabled {
+            match self.pointer_direction {
+                Some(direction) => format!("Pointer: {}", direction),
+                None => "".to_string(),
+            }
+        }
+        else if self.latched_buttons.is_empty() {
+            "".to_string()
+        }
+        else {
+            let buttons: Vec<String> = self.latched_buttons.iter().map(|button| button.to_string()).collect();
+            format!("Latched: {}", buttons.join(" + "))
         };
 
         container(
             column![
                 column![
-                    text(device_state),
+                    column![
+                        text(if self.dry_run { "Dry run: input is logged instead of simulated" } else { "" }),
+                        text(device_state),
+                        text(sim_state),
+                    ].align_items(Alignment::Center).spacing(5),
 
                     horizontal_rule(10),
 
-                    Column::with_children(
-                        self.config.hotkeys
-                            .iter()
-                            .enumerate()
-                            .map(|(index, config)| hotkey_form(index, config))
-                            .map(Element::from)
-                    )
-                        .spacing(30)
-                        .width(Length::Shrink),
+                    scrollable(
+                        column![
+                            calibration_settings,
+
+                            horizontal_rule(10),
+
+                            filter_settings,
+
+                            horizontal_rule(10),
 
-                    column![add_hotkey_button]
-                        .align_items(Alignment::Center)
-                        .width(Length::Fill)
-                        .spacing(20),
+                            artifact_settings,
+
+                            horizontal_rule(10),
+
+                            Column::with_children(
+                                self.config.hotkeys
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(index, config)| hotkey_form(index, config))
+                                    .map(Element::from)
+                            )
+                                .spacing(30)
+                                .width(Length::Shrink),
+
+                            column![add_hotkey_button]
+                                .align_items(Alignment::Center)
+                                .width(Length::Fill)
+                                .spacing(20),
+
+                            selection_settings,
+
+                            horizontal_rule(10),
+
+                            text("Gestures"),
+
+                            Column::with_children(
+                                self.config.gestures
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(index, gesture)| gesture_form(index, gesture))
+                            )
+                                .spacing(30)
+                                .width(Length::Shrink),
+
+                            column![add_gesture_button]
+                                .align_items(Alignment::Center)
+                                .width(Length::Fill)
+                                .spacing(20),
+
+                            gesture_settings,
+
+                            horizontal_rule(10),
+
+                            morse_settings,
+
+                            horizontal_rule(10),
+
+                            pointer_settings,
+
+                            horizontal_rule(10),
+
+                            gamepad_settings,
+
+                            horizontal_rule(10),
+
+                            max_hold_time_form,
+                        ]
+                            .spacing(30)
+                            .width(Length::Fill)
+                            .align_items(Alignment::Center)
+                    )
+                        .height(Length::Fill),
                 ]
                     .spacing(30)
                     .width(Length::Fill)
@@ -474,12 +1699,12 @@ fn make_icon() -> icon::Icon {
     icon::from_rgba(bytes, 32, 32).expect("Failed to load window icon")
 }
 
-pub fn run_application() -> Result<(), AppRunError> {
+pub fn run_application(breath_source: Arc<dyn BreathSource>, input_backend: Arc<dyn InputBackend>) -> Result<(), AppRunError> {
     let mut config_io = ConfigIO::new_sync()?;
     let mut config_locker = config_io.locker()?;
     let _lock_guard = config_locker.lock()?;
 
-    let flags = ApplicationFlags { config_io };
+    let flags = ApplicationFlags { config_io, breath_source, input_backend };
     let mut settings = Settings::with_flags(flags);
 
     // handle exits ourselves (Event::CloseRequested)
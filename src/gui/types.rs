@@ -1,30 +1,99 @@
-use iced::{Event};
+use iced::{Event, window};
 use iced::font::{Error as FontError};
 
-use crate::config::types::{BreathDirection, Config};
+use crate::config::types::{AccelerationCurve, BreathDirection, Config, GamepadLayout, HotkeyAction, HotkeyMode, MatchingPolicy, SelectionStrategy, Smoothing};
 use crate::device::types::{DeviceEvent};
-use crate::sim::types::Button;
+use crate::sim::types::{BreathInputSimEvent, Button, GamepadButton};
 
 #[derive(Debug, Clone)]
-pub enum HotkeyModifier {
-    Shift,
-    Ctrl,
-    Meta,
-    Alt,
+pub enum ChordChange {
+    // replace the button at the given position
+    ButtonChange(usize, Button),
+    // text has been typed in the search field that has focus
+    Search(String),
+    // the search field of the button at the given position has lost focus
+    SearchClosed(usize),
+    // add a modifier in front of the chord
+    Prepend,
+    RemoveFirst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyActionKind {
+    Button,
+    Macro,
+    Scroll,
+    GamepadButton,
+}
+
+pub const HOTKEY_ACTION_KINDS: [HotkeyActionKind; 4] = [
+    HotkeyActionKind::Button,
+    HotkeyActionKind::Macro,
+    HotkeyActionKind::Scroll,
+    HotkeyActionKind::GamepadButton,
+];
+
+impl HotkeyActionKind {
+    pub fn of(action: &HotkeyAction) -> Self {
+        match action {
+            HotkeyAction::Button => HotkeyActionKind::Button,
+            HotkeyAction::Macro(_) => HotkeyActionKind::Macro,
+            HotkeyAction::Scroll { .. } => HotkeyActionKind::Scroll,
+            HotkeyAction::GamepadButton(_) => HotkeyActionKind::GamepadButton,
+        }
+    }
+}
+
+impl std::fmt::Display for HotkeyActionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            HotkeyActionKind::Button => "Press button",
+            HotkeyActionKind::Macro => "Run macro",
+            HotkeyActionKind::Scroll => "Scroll",
+            HotkeyActionKind::GamepadButton => "Gamepad button",
+        };
+
+        write!(f, "{}", result)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum HotkeyChange {
     BreathDirectionChange(BreathDirection),
-    ButtonChange(Button),
+    ChordChange(ChordChange),
     ThresholdChange(String),
-    ModifierToggle(HotkeyModifier),
+    UpperThresholdChange(String),
+    ReleaseThresholdChange(String),
+    ActivationDelayChange(String),
+    ReleaseDelayChange(String),
+    ModeChange(HotkeyMode),
+    TapDurationChange(String),
+    MinRepeatRateChange(String),
+    MaxRepeatRateChange(String),
+    MinDurationChange(String),
+    MaxDurationChange(String),
+    MaxHoldTimeChange(String),
+    ActionChange(HotkeyActionKind),
+    MacroChange(String),
+    ScrollHorizontalToggle(bool),
+    ScrollSpeedChange(String),
+    GamepadButtonChange(GamepadButton),
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+pub enum GestureChange {
+    AppendBreath(BreathDirection),
+    RemoveBreath,
+    ChordChange(ChordChange),
     Delete,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     EventOccurred(Event),
+    // breath_input_sim has stopped after the window was asked to close
+    InputSimStopped(window::Id),
     ApplyDirtyConfig,
     WriteComplete(()),
     SymbolsFontLoadComplete(Result<(), FontError>),
@@ -32,8 +101,45 @@ pub enum Message {
     ConfigSaveComplete(Option<String>),
     NoticeConfirmed,
     DeviceEvent(DeviceEvent),
+    BreathInputSimEvent(BreathInputSimEvent),
     AddHotkey,
     HotkeyChange(usize, HotkeyChange),
+    ToggleHotkeySettings(usize),
+    SmoothingChange(Smoothing),
+    SmoothingWindowChange(String),
+    SpikeFilterToggle(bool),
+    BreathDeadzoneChange(String),
+    BreathCurveChange(BreathDirection, AccelerationCurve),
+    BreathSensitivityChange(BreathDirection, String),
+    ArtifactToggle(bool),
+    ArtifactSteepnessChange(String),
+    ArtifactMaxDurationChange(String),
+    CalibrationZero,
+    CalibrationReset,
+    AutoZeroToggle(bool),
+    PuffSelectionChange(SelectionStrategy),
+    SipSelectionChange(SelectionStrategy),
+    PeakCommitDelayChange(String),
+    PuffMatchingChange(MatchingPolicy),
+    SipMatchingChange(MatchingPolicy),
+    MaxHoldTimeChange(String),
+    AddGesture,
+    GestureChange(usize, GestureChange),
+    GestureThresholdChange(String),
+    GestureWindowChange(String),
+    MorseToggle(bool),
+    MorseThresholdChange(String),
+    MorseDashDurationChange(String),
+    MorseCharacterGapChange(String),
+    PointerToggle(bool),
+    PointerThresholdChange(String),
+    PointerMaxSpeedChange(String),
+    PointerAccelerationChange(AccelerationCurve),
+    PointerRotationIntervalChange(String),
+    GamepadToggle(bool),
+    GamepadLayoutChange(GamepadLayout),
+    GamepadDeadzoneChange(String),
+    GamepadResponseCurveChange(AccelerationCurve),
     LinkPress(String),
     LinkOpened(bool), // true if success, false if failed
 }
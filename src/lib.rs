@@ -1,5 +1,14 @@
 use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use clap::Parser;
+use crate::device::connection::BluetoothBreathSource;
+use crate::device::simulated::{SimulatedBreathSource, SimulatedCurve};
+use crate::device::source::BreathSource;
 use crate::gui::application::run_application;
+use crate::sim::backend::{InputBackend, InputBackendKind, RecordingBackend, create_input_backend};
+use crate::sim::safety::install_panic_hook;
 use crate::error::AppRunError;
 
 pub mod device;
@@ -34,7 +43,70 @@ pub fn init_logging() {
 
 }
 
-pub fn run(mut _args: env::Args) -> Result<(), AppRunError> {
-    run_application()?;
+/**
+ * The range (seconds) of the period of the simulated curve.
+ */
+const SIMULATE_PERIOD_RANGE: std::ops::RangeInclusive<f32> = 0.1..=3600.0;
+
+fn parse_simulate_period(input: &str) -> Result<f32, String> {
+    let period: f32 = input.parse().map_err(|err| format!("{}", err))?;
+    if !SIMULATE_PERIOD_RANGE.contains(&period) {
+        return Err(format!(
+            "must be between {} and {} seconds",
+            SIMULATE_PERIOD_RANGE.start(),
+            SIMULATE_PERIOD_RANGE.end(),
+        ));
+    }
+
+    Ok(period)
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version)]
+#[command(about = "Translates human breath input to mouse and keyboard hotkeys using the GroovTube BLE peripheral", long_about = None)]
+struct Args {
+    /// Do not connect to a GroovTube, instead simulate breath values using the given curve
+    #[arg(long, value_enum)]
+    simulate: Option<SimulatedCurve>,
+
+    /// The duration (seconds) of one cycle of the simulated curve
+    #[arg(long, default_value_t = 10.0, value_parser = parse_simulate_period)]
+    simulate_period: f32,
+
+    /// How keyboard and mouse input is simulated
+    #[arg(long, value_enum, default_value_t = InputBackendKind::Auto)]
+    input_backend: InputBackendKind,
+
+    /// Do not simulate keyboard and mouse input, only log what would have been pressed
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Like --dry-run, but also write the input events to the given file, one JSON object per line
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+}
+
+pub fn run(args: env::Args) -> Result<(), AppRunError> {
+    let args = Args::parse_from(args);
+
+    let input_backend: Arc<dyn InputBackend> = match (&args.record, args.dry_run) {
+        (Some(path), _) => Arc::new(
+            RecordingBackend::with_file(path).map_err(|source| AppRunError::RecordingError { source })?
+        ),
+        (None, true) => Arc::new(RecordingBackend::new()),
+        (None, false) => create_input_backend(args.input_backend)
+            .map_err(|source| AppRunError::InputBackendError { source })?,
+    };
+    install_panic_hook(input_backend.clone());
+
+    let breath_source: Arc<dyn BreathSource> = match args.simulate {
+        Some(curve) => Arc::new(SimulatedBreathSource::new(
+            curve,
+            Duration::from_secs_f32(args.simulate_period),
+        )),
+        None => Arc::new(BluetoothBreathSource),
+    };
+
+    run_application(breath_source, input_backend)?;
     Ok(())
 }
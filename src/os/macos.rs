@@ -1,9 +1,11 @@
-use core_foundation::base::{TCFType};
+use std::os::raw::{c_ulong, c_void};
+use core_foundation::base::{CFRelease, TCFType};
 use core_foundation::string::{CFString};
 use core_foundation::dictionary::{CFDictionary};
 use core_foundation::boolean::{CFBoolean};
 
 mod bindings {
+    use std::os::raw::{c_ulong, c_void};
     use core_foundation::string::CFStringRef;
     use core_foundation::dictionary::CFDictionaryRef;
 
@@ -12,6 +14,15 @@ mod bindings {
         pub fn AXIsProcessTrusted() -> bool;
         pub fn AXIsProcessTrustedWithOptions(options: CFDictionaryRef) -> bool;
     }
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        pub fn CGEventCreateKeyboardEvent(source: *const c_void, keycode: u16, key_down: bool) -> *mut c_void;
+        pub fn CGEventKeyboardSetUnicodeString(event: *mut c_void, length: c_ulong, string: *const u16);
+        pub fn CGEventPost(tap: u32, event: *mut c_void);
+    }
+
+    pub const K_CG_HID_EVENT_TAP: u32 = 0;
 }
 
 
@@ -32,3 +43,24 @@ pub fn check_accessibility_access(prompt: bool) -> bool {
 
     }
 }
+
+/**
+ * Type a character regardless of the keyboard layout.
+ */
+pub fn type_char(character: char) {
+    let mut units = [0; 2];
+    let units = character.encode_utf16(&mut units);
+
+    for key_down in [true, false] {
+        unsafe {
+            let event = bindings::CGEventCreateKeyboardEvent(std::ptr::null(), 0, key_down);
+            if event.is_null() {
+                return;
+            }
+
+            bindings::CGEventKeyboardSetUnicodeString(event, units.len() as c_ulong, units.as_ptr());
+            bindings::CGEventPost(bindings::K_CG_HID_EVENT_TAP, event);
+            CFRelease(event as *const c_void);
+        }
+    }
+}
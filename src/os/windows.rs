@@ -0,0 +1,64 @@
+use std::mem::size_of;
+use log::warn;
+
+mod bindings {
+    // winuser.h
+    pub const INPUT_KEYBOARD: u32 = 1;
+    pub const KEYEVENTF_KEYUP: u32 = 0x0002;
+    pub const KEYEVENTF_UNICODE: u32 = 0x0004;
+
+    #[repr(C)]
+    pub struct KeybdInput {
+        pub vk: u16,
+        pub scan: u16,
+        pub flags: u32,
+        pub time: u32,
+        pub extra_info: usize,
+    }
+
+    // INPUT contains a union of which the mouse variant is the largest, the padding makes up the difference
+    #[repr(C)]
+    pub struct Input {
+        pub kind: u32,
+        pub ki: KeybdInput,
+        pub padding: [u8; 8],
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        pub fn SendInput(count: u32, inputs: *const Input, size: i32) -> u32;
+    }
+}
+
+fn unicode_input(unit: u16, flags: u32) -> bindings::Input {
+    bindings::Input {
+        kind: bindings::INPUT_KEYBOARD,
+        ki: bindings::KeybdInput {
+            vk: 0,
+            scan: unit,
+            flags: bindings::KEYEVENTF_UNICODE | flags,
+            time: 0,
+            extra_info: 0,
+        },
+        padding: [0; 8],
+    }
+}
+
+/**
+ * Type a character regardless of the keyboard layout.
+ */
+pub fn type_char(character: char) {
+    let mut units = [0; 2];
+    let inputs: Vec<bindings::Input> = character.encode_utf16(&mut units)
+        .iter()
+        .flat_map(|unit| [unicode_input(*unit, 0), unicode_input(*unit, bindings::KEYEVENTF_KEYUP)])
+        .collect();
+
+    let sent = unsafe {
+        bindings::SendInput(inputs.len() as u32, inputs.as_ptr(), size_of::<bindings::Input>() as i32)
+    };
+
+    if sent as usize != inputs.len() {
+        warn!("Failed to type {:?}", character);
+    }
+}
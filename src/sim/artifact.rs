@@ -0,0 +1,242 @@
+use std::time::Duration;
+use log::{debug, info};
+use tokio::time::Instant;
+
+use crate::config::types::ArtifactConfig;
+use crate::device::constants::POLL_DELAY;
+
+// A steep rise in breath strength that might be an artifact
+struct SuspectRise {
+    // the breath value just before the rise, which is passed on until the rise is resolved
+    baseline: i8,
+    // true if the rise is towards puffing
+    puff: bool,
+    start: Instant,
+    // the highest strength during the rise
+    peak: i8,
+}
+
+/**
+ * Ignores short and very steep changes in breath strength, which are usually caused by coughing,
+ * sneezing or talking into the mouthpiece. A steep rise is held back until it either lasts longer
+ * than `max_duration` (it is intentional), or drops back to where it started (it is an artifact).
+ */
+pub struct ArtifactDetector {
+    enabled: bool,
+    steepness: i8,
+    max_duration: Duration,
+    // the most recent breath value that was passed to handle_breath(), and when
+    previous_value: i8,
+    previous_at: Option<Instant>,
+    suspect: Option<SuspectRise>,
+    // the most recent value returned by the detector
+    output: i8,
+    // the number of artifacts that have been ignored so far
+    rejected: u32,
+}
+
+impl ArtifactDetector {
+    pub fn new(config: &ArtifactConfig) -> Self {
+        ArtifactDetector {
+            enabled: config.enabled,
+            steepness: config.steepness(),
+            max_duration: config.max_duration(),
+            previous_value: 0,
+            previous_at: None,
+            suspect: None,
+            output: 0,
+            rejected: 0,
+        }
+    }
+
+    pub fn set_config(&mut self, config: &ArtifactConfig) {
+        self.enabled = config.enabled;
+        self.steepness = config.steepness();
+        self.max_duration = config.max_duration();
+    }
+
+    pub fn reset(&mut self) {
+        self.previous_value = 0;
+        self.previous_at = None;
+        self.suspect = None;
+        self.output = 0;
+    }
+
+    pub fn rejected(&self) -> u32 {
+        self.rejected
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.suspect.as_ref().map(|suspect| suspect.start + self.max_duration)
+    }
+
+    /**
+     * Returns the breath value that should be used instead, if it has changed.
+     */
+    pub fn handle_breath(&mut self, breath_value: i8, now: Instant) -> Option<i8> {
+        let previous_value = self.previous_value;
+        let previous_at = self.previous_at;
+        self.previous_value = breath_value;
+        self.previous_at = Some(now);
+
+        if !self.enabled {
+            self.suspect = None;
+            return self.set_output(breath_value);
+        }
+
+        if let Some(suspect) = &mut self.suspect {
+            let strength = if suspect.puff { breath_value } else { -breath_value };
+            suspect.peak = suspect.peak.max(strength);
+
+            let baseline_strength = if suspect.puff { suspect.baseline } else { -suspect.baseline };
+            if strength > baseline_strength.max(0) {
+                // still rising or holding, the outcome is decided by handle_timer()
+                return None;
+            }
+
+            info!(
+                "Ignored a breath artifact that reached {}% {} within {} ms",
+                suspect.peak,
+                if suspect.puff { "puff" } else { "sip" },
+                (now - suspect.start).as_millis(),
+            );
+            self.suspect = None;
+            self.rejected += 1;
+            return self.set_output(breath_value);
+        }
+
+        // the device only reports changes, so the rise happened within the last poll interval at most
+        let interval = previous_at
+            .map(|previous_at| now - previous_at)
+            .unwrap_or_default()
+            .max(Duration::from_millis(POLL_DELAY));
+
+        let puff = breath_value > 0;
+        let strength = i16::from(breath_value).abs();
+        let previous_strength = match (puff, previous_value) {
+            (true, previous) if previous > 0 => i16::from(previous),
+            (false, previous) if previous < 0 => -i16::from(previous),
+            _ => 0,
+        };
+        let rise = f64::from(strength - previous_strength) * POLL_DELAY as f64 / interval.as_millis() as f64;
+
+        if breath_value != 0 && rise >= f64::from(self.steepness) {
+            debug!("Steep rise from {}% to {}%, holding it back", previous_value, breath_value);
+            self.suspect = Some(SuspectRise {
+                baseline: self.output,
+                puff,
+                start: now,
+                peak: breath_value.saturating_abs(),
+            });
+            return None;
+        }
+
+        self.set_output(breath_value)
+    }
+
+    /**
+     * Returns the breath value that should be used instead, if it has changed.
+     */
+    pub fn handle_timer(&mut self, now: Instant) -> Option<i8> {
+        match self.next_deadline() {
+            Some(deadline) if deadline <= now => {
+                debug!("Steep rise lasted longer than {:?}, it is intentional", self.max_duration);
+                self.suspect = None;
+                self.set_output(self.previous_value)
+            },
+            _ => None,
+        }
+    }
+
+    fn set_output(&mut self, breath_value: i8) -> Option<i8> {
+        if breath_value == self.output {
+            return None;
+        }
+
+        self.output = breath_value;
+        Some(breath_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::advance;
+    use super::*;
+
+    fn enabled_detector() -> ArtifactDetector {
+        ArtifactDetector::new(&ArtifactConfig { enabled: true, ..ArtifactConfig::default() })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn short_steep_rise_is_rejected() {
+        let mut detector = enabled_detector();
+
+        assert_eq!(detector.handle_breath(0, Instant::now()), None);
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(60, Instant::now()), None);
+        assert_eq!(detector.next_deadline(), Some(Instant::now() + detector.max_duration));
+
+        advance(Duration::from_millis(50)).await;
+        assert_eq!(detector.handle_breath(80, Instant::now()), None);
+        assert_eq!(detector.handle_timer(Instant::now()), None);
+
+        advance(Duration::from_millis(50)).await;
+        assert_eq!(detector.handle_breath(0, Instant::now()), None);
+        assert_eq!(detector.next_deadline(), None);
+        assert_eq!(detector.rejected(), 1);
+
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(-60, Instant::now()), None);
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(0, Instant::now()), None);
+        assert_eq!(detector.rejected(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn long_steep_rise_is_released_after_max_duration() {
+        let mut detector = enabled_detector();
+
+        assert_eq!(detector.handle_breath(0, Instant::now()), None);
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(60, Instant::now()), None);
+
+        advance(detector.max_duration - Duration::from_millis(1)).await;
+        assert_eq!(detector.handle_timer(Instant::now()), None);
+
+        advance(Duration::from_millis(1)).await;
+        assert_eq!(detector.handle_timer(Instant::now()), Some(60));
+        assert_eq!(detector.next_deadline(), None);
+        assert_eq!(detector.rejected(), 0);
+
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(0, Instant::now()), Some(0));
+        assert_eq!(detector.rejected(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gradual_rise_is_passed_on() {
+        let mut detector = enabled_detector();
+
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(20, Instant::now()), Some(20));
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(40, Instant::now()), Some(40));
+
+        // a steep value change spread over several poll intervals
+        advance(Duration::from_millis(50)).await;
+        assert_eq!(detector.handle_breath(100, Instant::now()), Some(100));
+        assert_eq!(detector.next_deadline(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disabled_detector_passes_everything_on() {
+        let mut detector = ArtifactDetector::new(&ArtifactConfig::default());
+
+        assert_eq!(detector.handle_breath(0, Instant::now()), None);
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(100, Instant::now()), Some(100));
+        advance(Duration::from_millis(10)).await;
+        assert_eq!(detector.handle_breath(0, Instant::now()), Some(0));
+        assert_eq!(detector.rejected(), 0);
+    }
+}
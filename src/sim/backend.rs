@@ -0,0 +1,333 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use clap::ValueEnum;
+use log::{info, warn};
+use rdev::{EventType, display_size, simulate};
+use serde::Serialize;
+
+use crate::sim::cursor::{cursor_position, set_cursor_position, start_cursor_tracking};
+use crate::sim::types::Button;
+
+/**
+ * Performs the keyboard and mouse input that input_sim decides on.
+ */
+pub trait InputBackend: Send + Sync {
+    fn press(&self, button: &Button);
+    fn release(&self, button: &Button);
+    // move the mouse pointer relative to its current position, in pixels
+    fn move_pointer(&self, dx: f64, dy: f64);
+    // positive is right and up
+    fn scroll(&self, delta_x: i64, delta_y: i64);
+
+    // true if the input does not reach the OS
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+/**
+ * Type a character by entering its code point after Ctrl+Shift+U, which GTK applications and the
+ * IBus input method on Linux understand. Used where the OS has no way to type a character directly.
+ */
+#[cfg(target_os = "linux")]
+pub fn type_with_unicode_input(backend: &dyn InputBackend, character: char) {
+    let prefix = [Button::ControlLeft, Button::ShiftLeft, Button::KeyU];
+    for button in &prefix {
+        backend.press(button);
+    }
+    for button in prefix.iter().rev() {
+        backend.release(button);
+    }
+
+    let hex_digits = [
+        Button::Num0, Button::Num1, Button::Num2, Button::Num3, Button::Num4, Button::Num5, Button::Num6, Button::Num7,
+        Button::Num8, Button::Num9, Button::KeyA, Button::KeyB, Button::KeyC, Button::KeyD, Button::KeyE, Button::KeyF,
+    ];
+    for digit in format!("{:x}", u32::from(character)).chars() {
+        let button = hex_digits[digit.to_digit(16).unwrap_or(0) as usize];
+        backend.press(&button);
+        backend.release(&button);
+    }
+
+    backend.press(&Button::Space);
+    backend.release(&Button::Space);
+}
+
+/**
+ * Simulates real keyboard and mouse input using rdev.
+ */
+pub struct RdevBackend {
+    // pixels, None if the size could not be determined
+    screen_size: OnceLock<Option<(f64, f64)>>,
+}
+
+impl RdevBackend {
+    pub fn new() -> Self {
+        RdevBackend {
+            screen_size: OnceLock::new(),
+        }
+    }
+
+    fn send(event_type: &EventType) {
+        if let Err(err) = simulate(event_type) {
+            warn!("Failed to simulate {:?}: {:?}", event_type, err);
+        }
+    }
+}
+
+/**
+ * macOS scrolls by pixels instead of lines, this is roughly the distance of one notch.
+ */
+#[cfg(target_os = "macos")]
+const PIXELS_PER_SCROLL_NOTCH: i64 = 10;
+
+impl InputBackend for RdevBackend {
+    fn press(&self, button: &Button) {
+        if let Button::Char(character) = button {
+            #[cfg(target_os = "linux")]
+            type_with_unicode_input(self, *character);
+            #[cfg(target_os = "windows")]
+            crate::os::windows::type_char(*character);
+            #[cfg(target_os = "macos")]
+            crate::os::macos::type_char(*character);
+        }
+        else if let Some(btn) = button.rdev_mouse_button() {
+            Self::send(&EventType::ButtonPress(btn));
+        }
+        else if let Some(key) = button.rdev_key() {
+            Self::send(&EventType::KeyPress(key));
+        }
+    }
+
+    fn release(&self, button: &Button) {
+        if let Some(btn) = button.rdev_mouse_button() {
+            Self::send(&EventType::ButtonRelease(btn));
+        }
+        else if let Some(key) = button.rdev_key() {
+            Self::send(&EventType::KeyRelease(key));
+        }
+    }
+
+    // rdev can only move the pointer to an absolute position, so the movement starts from the
+    // tracked position of the pointer. The pointer does not leave the screen.
+    fn move_pointer(&self, dx: f64, dy: f64) {
+        start_cursor_tracking();
+
+        let screen_size = *self.screen_size.get_or_init(|| match display_size() {
+            Ok((width, height)) => Some((width as f64, height as f64)),
+            Err(err) => {
+                warn!("Unable to determine the size of the screen: {:?}", err);
+                None
+            },
+        });
+
+        // the position is unknown until the mouse has been moved, start from the center of the screen
+        let (x, y) = cursor_position()
+            .or(screen_size.map(|(width, height)| (width / 2.0, height / 2.0)))
+            .unwrap_or_default();
+        let (mut new_x, mut new_y) = (x + dx, y + dy);
+
+        if let Some((width, height)) = screen_size {
+            new_x = new_x.clamp(0.0, (width - 1.0).max(0.0));
+            new_y = new_y.clamp(0.0, (height - 1.0).max(0.0));
+        }
+
+        Self::send(&EventType::MouseMove { x: new_x, y: new_y });
+        set_cursor_position((new_x, new_y));
+    }
+
+    fn scroll(&self, delta_x: i64, delta_y: i64) {
+        #[cfg(target_os = "macos")]
+        let (delta_x, delta_y) = (delta_x * PIXELS_PER_SCROLL_NOTCH, delta_y * PIXELS_PER_SCROLL_NOTCH);
+
+        Self::send(&EventType::Wheel { delta_x, delta_y });
+    }
+}
+
+impl Default for RdevBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InputEvent {
+    Press(Button),
+    Release(Button),
+    #[serde(rename_all = "camelCase")]
+    MovePointer { dx: f64, dy: f64 },
+    #[serde(rename_all = "camelCase")]
+    Scroll { delta_x: i64, delta_y: i64 },
+}
+
+impl std::fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputEvent::Press(button) => write!(f, "press {}", button),
+            InputEvent::Release(button) => write!(f, "release {}", button),
+            InputEvent::MovePointer { dx, dy } => write!(f, "move pointer by {}, {}", dx, dy),
+            InputEvent::Scroll { delta_x, delta_y } => write!(f, "scroll {}, {}", delta_x, delta_y),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedEvent {
+    // time since the backend was created
+    #[serde(serialize_with = "serialize_millis", rename = "elapsedMs")]
+    pub elapsed: Duration,
+    pub event: InputEvent,
+}
+
+fn serialize_millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+/**
+ * How many of the most recent events RecordingBackend keeps in memory. Older events are only in the
+ * log and the recording file.
+ */
+const MAX_RECORDED_EVENTS: usize = 1000;
+
+/**
+ * Records input events instead of simulating them ("dry run"). The most recent events are kept in
+ * memory, every event is logged and optionally written to a file as one JSON object per line, as
+ * soon as it happens.
+ */
+pub struct RecordingBackend {
+    start: Instant,
+    events: Mutex<VecDeque<RecordedEvent>>,
+    file: Option<Mutex<LineWriter<File>>>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        RecordingBackend {
+            start: Instant::now(),
+            events: Mutex::new(VecDeque::new()),
+            file: None,
+        }
+    }
+
+    pub fn with_file(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+
+        Ok(RecordingBackend {
+            file: Some(Mutex::new(LineWriter::new(file))),
+            ..RecordingBackend::new()
+        })
+    }
+
+    // The most recent events, at most MAX_RECORDED_EVENTS
+    pub fn events(&self) -> Vec<RecordedEvent> {
+        self.events.lock().unwrap_or_else(|err| err.into_inner()).iter().cloned().collect()
+    }
+
+    fn record(&self, event: InputEvent) {
+        let recorded = RecordedEvent {
+            elapsed: self.start.elapsed(),
+            event,
+        };
+        info!("Dry run: {}", recorded.event);
+
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+            let result = serde_json::to_string(&recorded)
+                .map_err(io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line));
+
+            if let Err(err) = result {
+                warn!("Failed to write to the recording file: {}", err);
+            }
+        }
+
+        let mut events = self.events.lock().unwrap_or_else(|err| err.into_inner());
+        if events.len() >= MAX_RECORDED_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(recorded);
+    }
+}
+
+impl Default for RecordingBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputBackend for RecordingBackend {
+    fn press(&self, button: &Button) {
+        self.record(InputEvent::Press(*button));
+    }
+
+    fn release(&self, button: &Button) {
+        self.record(InputEvent::Release(*button));
+    }
+
+    fn move_pointer(&self, dx: f64, dy: f64) {
+        self.record(InputEvent::MovePointer { dx, dy });
+    }
+
+    fn scroll(&self, delta_x: i64, delta_y: i64) {
+        self.record(InputEvent::Scroll { delta_x, delta_y });
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputBackendKind {
+    // uinput if this is a Linux session without X11, otherwise rdev
+    Auto,
+    // X11 on Linux, the native APIs on Windows and macOS
+    Rdev,
+    // a virtual input device on Linux, which also works with Wayland
+    Uinput,
+}
+
+#[cfg(target_os = "linux")]
+fn create_uinput_backend() -> io::Result<Arc<dyn InputBackend>> {
+    Ok(Arc::new(crate::sim::uinput::UinputBackend::new()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn create_uinput_backend() -> io::Result<Arc<dyn InputBackend>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "uinput is only available on Linux"))
+}
+
+// rdev simulates input through X11 on Linux, which does not reach Wayland applications
+fn x11_available() -> bool {
+    if !cfg!(target_os = "linux") {
+        return true;
+    }
+
+    std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_some()
+}
+
+/**
+ * Create the backend that simulates real input. In the Auto mode, uinput is preferred if X11 is
+ * unavailable, falling back to rdev if the uinput device can not be created.
+ */
+pub fn create_input_backend(kind: InputBackendKind) -> io::Result<Arc<dyn InputBackend>> {
+    match kind {
+        InputBackendKind::Rdev => Ok(Arc::new(RdevBackend::new())),
+        InputBackendKind::Uinput => create_uinput_backend(),
+        InputBackendKind::Auto if x11_available() => Ok(Arc::new(RdevBackend::new())),
+        InputBackendKind::Auto => {
+            info!("X11 is not available, using uinput to simulate input");
+
+            create_uinput_backend().or_else(|err| {
+                warn!("Failed to create uinput device, falling back to rdev: {}", err);
+                Ok(Arc::new(RdevBackend::new()))
+            })
+        },
+    }
+}
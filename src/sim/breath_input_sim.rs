@@ -1,77 +1,1220 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use futures::future::pending;
+use iced::subscription::{self, Subscription};
 use indexmap::IndexSet;
 use tokio::spawn;
 use tokio::task::JoinHandle;
-use futures::channel::mpsc::{channel, Sender};
+use tokio::time::{sleep_until, Instant};
+use futures::channel::mpsc::{channel, Receiver, Sender};
 use tokio_util::sync::CancellationToken;
 use futures::{StreamExt, SinkExt};
+use log::{debug, info, warn};
 
-use crate::config::types::{HotkeyConfig, BreathDirection};
-use crate::device::types::DeviceEvent;
+use crate::config::types::{BREATH_DIRECTIONS, AccelerationCurve, ArtifactConfig, CalibrationConfig, Config, FilterConfig, GamepadConfig, GestureConfig, HotkeyAction, HotkeyConfig, HotkeyMode, BreathDirection, MatchingPolicy, SelectionStrategy, DEFAULT_GESTURE_THRESHOLD, DEFAULT_MORSE_THRESHOLD, DEFAULT_POINTER_MAX_SPEED, DEFAULT_POINTER_THRESHOLD, DEFAULT_SCROLL_SPEED, DEFAULT_TAP_DURATION, strength_above_threshold};
+use crate::device::types::{DeviceEvent, DeviceState};
+use crate::sim::artifact::ArtifactDetector;
+use crate::sim::backend::InputBackend;
+use crate::sim::filter::BreathFilter;
+use crate::sim::gamepad::{GamepadOutput, HeldGamepadButtons};
+use crate::sim::gesture::{GestureBreath, GestureRecognizer};
 use crate::sim::input_sim::input_sim_task;
-use crate::sim::types::{HeldButtons, BreathInputSimCommand, InputSimCommand, Button};
+use crate::sim::morse::MorseDecoder;
+use crate::sim::pointer::PointerController;
+use crate::sim::types::{HeldButtons, BreathInputSimCommand, BreathInputSimEvent, InputSimCommand, Button, RepeatTap};
 
-pub fn breath_input_sim(cancel: CancellationToken) -> (Sender<DeviceEvent>, Sender<BreathInputSimCommand>, JoinHandle<()>) {
+fn hotkey_buttons(hotkey: &HotkeyConfig) -> HeldButtons {
+    hotkey.buttons.iter().copied().collect()
+}
+
+/**
+ * How often (milliseconds) a scroll hotkey scrolls while it is pressed.
+ */
+const SCROLL_TICK_INTERVAL: u64 = 50;
+
+// Scroll the given number of notches, puffs scroll down or right, sips scroll up or left
+fn scroll_command(direction: BreathDirection, horizontal: bool, notches: i64) -> InputSimCommand {
+    match (horizontal, direction) {
+        (false, BreathDirection::Puff) => InputSimCommand::Scroll(0, -notches),
+        (false, BreathDirection::Sip) => InputSimCommand::Scroll(0, notches),
+        (true, BreathDirection::Puff) => InputSimCommand::Scroll(notches, 0),
+        (true, BreathDirection::Sip) => InputSimCommand::Scroll(-notches, 0),
+    }
+}
+
+// The command that taps the hotkey once, runs its macro, or scrolls one notch. Gamepad buttons
+// are not tapped, they are held for as long as the hotkey is pressed
+fn hotkey_activation(hotkey: &HotkeyConfig) -> Option<InputSimCommand> {
+    match &hotkey.action {
+        HotkeyAction::Button => Some(InputSimCommand::Tap(hotkey_buttons(hotkey), hotkey.tap_duration())),
+        HotkeyAction::Macro(steps) => Some(InputSimCommand::RunMacro(steps.clone())),
+        HotkeyAction::Scroll { horizontal, .. } => Some(scroll_command(hotkey.breath_direction, *horizontal, 1)),
+        HotkeyAction::GamepadButton(_) => None,
+    }
+}
+
+fn gesture_buttons(gesture: &GestureConfig) -> HeldButtons {
+    gesture.buttons.iter().copied().collect()
+}
+
+fn morse_tap(button: Button) -> InputSimCommand {
+    let mut buttons: HeldButtons = IndexSet::new();
+    buttons.insert(button);
+    InputSimCommand::Tap(buttons, Duration::from_millis(u64::from(DEFAULT_TAP_DURATION)))
+}
+
+// Whether a pressed hotkey keeps something going, rather than only tapping when it is pressed or released
+fn holds_input(hotkey: &HotkeyConfig) -> bool {
+    matches!(
+        (hotkey.mode, &hotkey.action),
+        (HotkeyMode::Hold, HotkeyAction::Button) |
+            (HotkeyMode::Hold, HotkeyAction::Scroll { .. }) |
+            (HotkeyMode::Repeat, HotkeyAction::Button) |
+            (_, HotkeyAction::GamepadButton(_))
+    )
+}
+
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// identifies a hotkey by its index in puff_hotkeys or sip_hotkeys
+type HotkeyIndex = (BreathDirection, usize);
+
+struct BreathInputSimState {
+    // the config that the state has been set up from, without the settings that are applied
+    // outside of the state
+    config: Option<Config>,
+    puff_hotkeys: Vec<HotkeyConfig>,
+    sip_hotkeys: Vec<HotkeyConfig>,
+    // the hotkeys that match the current breath value, ordered by descending threshold
+    active_hotkeys: Vec<HotkeyIndex>,
+    // the hotkeys that are currently pressed, in the order in which they were pressed. these lag
+    // behind active_hotkeys because of the activation delay and release delay
+    pressed_hotkeys: Vec<HotkeyIndex>,
+    // active hotkeys that will be pressed at the given instant, unless the breath changes before then
+    pending_presses: Vec<(HotkeyIndex, Instant)>,
+    // pressed hotkeys that will be released at the given instant, unless the breath changes before then
+    pending_releases: Vec<(HotkeyIndex, Instant)>,
+    // when each of the pressed hotkeys was pressed
+    pressed_since: Vec<(HotkeyIndex, Instant)>,
+    // how long a hotkey may be held continuously, unless it has its own maximum hold time
+    max_hold_time: Option<Duration>,
+    // hotkeys that have been released because they were held for too long. these cannot be pressed
+    // again until the breath returns to neutral
+    tripped_hotkeys: Vec<HotkeyIndex>,
+    // hotkeys that have been released because they were held for too long, and for how long they
+    // were held. the GUI has not been told about these yet
+    exceeded_hold_times: Vec<(HotkeyConfig, Duration)>,
+    // which of the matching hotkeys are active
+    puff_matching: MatchingPolicy,
+    sip_matching: MatchingPolicy,
+    // with the ExclusiveHighest policy, hotkeys with a lower threshold than the hotkeys that have
+    // been pressed during the current breath are ignored
+    exclusive_threshold: Option<i8>,
+    // buttons that remain pressed after the breath ends, because of hotkeys in latch mode
+    latched_buttons: HeldButtons,
+    // the latch hotkeys whose buttons are latched, and when they were latched
+    latched_since: Vec<(HotkeyConfig, Instant)>,
+    gestures: Vec<GestureConfig>,
+    gesture_recognizer: GestureRecognizer,
+    // the most recent breath value of the device
+    latest_breath_value: i8,
+    // the most recent breath value that the hotkeys have seen. this is neutral while the breath
+    // might be part of a gesture
+    breath_value: i8,
+    // the direction and start of the breath that is currently in progress
+    breath_start: Option<(BreathDirection, Instant)>,
+    // the highest absolute breath value of the breath that is currently in progress
+    breath_peak: i8,
+    // the last instant at which breath_peak increased
+    breath_peak_at: Option<Instant>,
+    // how a hotkey is chosen while the breath is getting stronger
+    puff_selection: SelectionStrategy,
+    sip_selection: SelectionStrategy,
+    peak_commit_delay: Duration,
+    // true once the hotkey of the current breath has been chosen, with the PeakCommit strategy
+    peak_committed: bool,
+    // the breath has stopped getting stronger if its peak has not changed by this instant
+    commit_deadline: Option<Instant>,
+    // true if a hotkey with a minimum duration has been pressed during the current breath
+    long_hotkey_pressed: bool,
+    // the next instant at which a hotkey with a minimum duration becomes eligible
+    duration_deadline: Option<Instant>,
+    // true if any hotkey runs a macro
+    has_macros: bool,
+    // the pressed scroll hotkey scrolls again at this instant
+    scroll_deadline: Option<Instant>,
+    // notches that have not been scrolled yet, because they add up to less than one
+    scroll_remainder: f64,
+    // if enabled, breath is only used for morse code
+    morse_enabled: bool,
+    morse_decoder: MorseDecoder,
+    // if enabled, breath is only used to move the mouse pointer
+    pointer_enabled: bool,
+    pointer_controller: PointerController,
+}
+
+impl BreathInputSimState {
+    fn new() -> Self {
+        BreathInputSimState {
+            config: None,
+            puff_hotkeys: Vec::new(),
+            sip_hotkeys: Vec::new(),
+            active_hotkeys: Vec::new(),
+            pressed_hotkeys: Vec::new(),
+            pending_presses: Vec::new(),
+            pending_releases: Vec::new(),
+            pressed_since: Vec::new(),
+            max_hold_time: None,
+            tripped_hotkeys: Vec::new(),
+            exceeded_hold_times: Vec::new(),
+            puff_matching: MatchingPolicy::FirstMatch,
+            sip_matching: MatchingPolicy::FirstMatch,
+            exclusive_threshold: None,
+            latched_buttons: IndexSet::new(),
+            latched_since: Vec::new(),
+            gestures: Vec::new(),
+            gesture_recognizer: GestureRecognizer::new(DEFAULT_GESTURE_THRESHOLD, Duration::ZERO),
+            latest_breath_value: 0,
+            breath_value: 0,
+            breath_start: None,
+            breath_peak: 0,
+            breath_peak_at: None,
+            puff_selection: SelectionStrategy::Immediate,
+            sip_selection: SelectionStrategy::Immediate,
+            peak_commit_delay: Duration::ZERO,
+            peak_committed: false,
+            commit_deadline: None,
+            long_hotkey_pressed: false,
+            duration_deadline: None,
+            has_macros: false,
+            scroll_deadline: None,
+            scroll_remainder: 0.0,
+            morse_enabled: false,
+            morse_decoder: MorseDecoder::new(DEFAULT_MORSE_THRESHOLD, Duration::ZERO, Duration::ZERO),
+            pointer_enabled: false,
+            pointer_controller: PointerController::new(
+                DEFAULT_POINTER_THRESHOLD,
+                DEFAULT_POINTER_MAX_SPEED,
+                AccelerationCurve::Linear,
+                Duration::ZERO,
+            ),
+        }
+    }
+
+    fn hotkeys(&self, direction: BreathDirection) -> &Vec<HotkeyConfig> {
+        match direction {
+            BreathDirection::Puff => &self.puff_hotkeys,
+            BreathDirection::Sip => &self.sip_hotkeys,
+        }
+    }
+
+    fn hotkey(&self, (direction, index): HotkeyIndex) -> Option<&HotkeyConfig> {
+        self.hotkeys(direction).get(index)
+    }
+
+    // Returns false if nothing has changed for the state. Otherwise, the hotkeys that were pressed
+    // have been released
+    fn set_config(&mut self, config: Config) -> bool {
+        // changing these does not interrupt the breath in progress
+        let config = Config {
+            filter: FilterConfig::default(),
+            artifacts: ArtifactConfig::default(),
+            gamepad: GamepadConfig::default(),
+            calibration: CalibrationConfig::default(),
+            ..config
+        };
+        if self.config.as_ref() == Some(&config) {
+            return false;
+        }
+        self.config = Some(config.clone());
+        let tripped_hotkeys: Vec<HotkeyConfig> = self.tripped_hotkeys.iter()
+            .filter_map(|index| self.hotkey(*index))
+            .cloned()
+            .collect();
+
+        self.gesture_recognizer = GestureRecognizer::new(config.gesture_threshold(), config.gesture_window());
+        self.peak_commit_delay = config.peak_commit_delay();
+        self.max_hold_time = config.max_hold_time();
+        self.gestures = config.gestures;
+        self.morse_enabled = config.morse.enabled;
+        self.morse_decoder = MorseDecoder::new(
+            config.morse.threshold(),
+            config.morse.dash_duration(),
+            config.morse.character_gap(),
+        );
+        self.puff_selection = config.puff_selection;
+        self.sip_selection = config.sip_selection;
+        self.puff_matching = config.puff_matching;
+        self.sip_matching = config.sip_matching;
+        self.pointer_enabled = config.pointer.enabled;
+        self.pointer_controller = PointerController::new(
+            config.pointer.threshold(),
+            config.pointer.max_speed(),
+            config.pointer.acceleration,
+            config.pointer.rotation_interval(),
+        );
+
+        let mut hotkeys = config.hotkeys;
+        self.has_macros = hotkeys.iter().any(|hotkey| matches!(hotkey.action, HotkeyAction::Macro(_)));
+        // sort descending by threshold, None last. hotkeys with a longer minimum duration go first,
+        // so that they take precedence once the breath has lasted long enough
+        hotkeys.sort_by(
+            |a, b|
+            b.threshold.unwrap_or(0).cmp(&a.threshold.unwrap_or(0))
+                .then_with(|| b.min_duration.unwrap_or(0).cmp(&a.min_duration.unwrap_or(0)))
+        );
+
+        (self.puff_hotkeys, self.sip_hotkeys) = hotkeys
+            .into_iter()
+            .partition(|hotkey| hotkey.breath_direction == BreathDirection::Puff);
+
+        let latched_buttons = std::mem::take(&mut self.latched_buttons);
+        let latched_since = std::mem::take(&mut self.latched_since);
+        self.reset();
+        self.keep_latches(latched_buttons, latched_since);
+        self.keep_tripped_hotkeys(&tripped_hotkeys);
+        true
+    }
+
+    // Hotkeys that were released because they were held for too long are still not pressed until
+    // the breath returns to neutral, even if they have been edited
+    fn keep_tripped_hotkeys(&mut self, tripped_hotkeys: &[HotkeyConfig]) {
+        for direction in BREATH_DIRECTIONS {
+            let hotkeys = self.hotkeys(direction);
+            let tripped: Vec<HotkeyIndex> = (0..hotkeys.len())
+                .filter(|index| tripped_hotkeys.iter().any(|tripped| {
+                    tripped.breath_direction == direction &&
+                        tripped.buttons == hotkeys[*index].buttons &&
+                        tripped.action == hotkeys[*index].action
+                }))
+                .map(|index| (direction, index))
+                .collect();
+            self.tripped_hotkeys.extend(tripped);
+        }
+    }
+
+    // Restore the latches of the previous config, as long as a hotkey can still unlatch them
+    fn keep_latches(&mut self, mut latched_buttons: HeldButtons, latched_since: Vec<(HotkeyConfig, Instant)>) {
+        let mut kept_buttons: HeldButtons = IndexSet::new();
+
+        for (hotkey, since) in latched_since {
+            let new_hotkey = self.puff_hotkeys.iter().chain(self.sip_hotkeys.iter()).find(|new_hotkey| {
+                new_hotkey.mode == HotkeyMode::Latch &&
+                    new_hotkey.action == HotkeyAction::Button &&
+                    new_hotkey.buttons == hotkey.buttons
+            });
+
+            if let Some(new_hotkey) = new_hotkey.cloned() {
+                kept_buttons.extend(hotkey_buttons(&new_hotkey));
+                self.latched_since.push((new_hotkey, since));
+            }
+        }
+
+        latched_buttons.retain(|button| kept_buttons.contains(button));
+        self.latched_buttons = latched_buttons;
+    }
+
+    fn reset(&mut self) {
+        self.active_hotkeys.clear();
+        self.pressed_hotkeys.clear();
+        self.pending_presses.clear();
+        self.pending_releases.clear();
+        self.pressed_since.clear();
+        self.tripped_hotkeys.clear();
+        self.exclusive_threshold = None;
+        self.latched_buttons.clear();
+        self.latched_since.clear();
+        self.gesture_recognizer.reset();
+        self.latest_breath_value = 0;
+        self.breath_value = 0;
+        self.breath_start = None;
+        self.breath_peak = 0;
+        self.breath_peak_at = None;
+        self.peak_committed = false;
+        self.commit_deadline = None;
+        self.long_hotkey_pressed = false;
+        self.duration_deadline = None;
+        self.scroll_deadline = None;
+        self.scroll_remainder = 0.0;
+        self.morse_decoder.reset();
+        self.pointer_controller.reset();
+    }
+
+    fn toggle_latch(&mut self, hotkey: &HotkeyConfig, now: Instant) {
+        let buttons = hotkey_buttons(hotkey);
+        if buttons.is_subset(&self.latched_buttons) {
+            self.unlatch(&buttons);
+        }
+        else {
+            self.latched_buttons.extend(buttons);
+            self.latched_since.retain(|(latched, _)| latched != hotkey);
+            self.latched_since.push((hotkey.clone(), now));
+        }
+    }
+
+    // Release latched buttons. A latch hotkey stays latched while any of its buttons are
+    fn unlatch(&mut self, buttons: &HeldButtons) {
+        self.latched_buttons.retain(|button| !buttons.contains(button));
+
+        let latched_buttons = &self.latched_buttons;
+        self.latched_since.retain(|(hotkey, _)| hotkey.buttons.iter().any(|button| latched_buttons.contains(button)));
+    }
+
+    fn selection_strategy(&self, direction: BreathDirection) -> SelectionStrategy {
+        match direction {
+            BreathDirection::Puff => self.puff_selection,
+            BreathDirection::Sip => self.sip_selection,
+        }
+    }
+
+    fn matching_policy(&self, direction: BreathDirection) -> MatchingPolicy {
+        match direction {
+            BreathDirection::Puff => self.puff_matching,
+            BreathDirection::Sip => self.sip_matching,
+        }
+    }
+
+    // Keep the matches that the matching policy allows. Matches are ordered by descending
+    // threshold, so the first match has the highest threshold
+    fn select_matches(&self, direction: BreathDirection, matches: impl Iterator<Item = usize>) -> Vec<usize> {
+        match self.matching_policy(direction) {
+            MatchingPolicy::AllMatch => matches.collect(),
+            MatchingPolicy::FirstMatch | MatchingPolicy::ExclusiveHighest => matches.take(1).collect(),
+        }
+    }
+
+    // Activate a hotkey once, without holding it
+    fn tap_hotkey(&mut self, hotkey: &HotkeyConfig, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        match (hotkey.mode, &hotkey.action) {
+            (HotkeyMode::Latch, HotkeyAction::Button) => self.toggle_latch(hotkey, now),
+            _ => taps.extend(hotkey_activation(hotkey)),
+        }
+    }
+
+    // How long the breath that is currently in progress has lasted
+    fn breath_duration(&self, now: Instant) -> Duration {
+        self.breath_start.map(|(_, start)| now - start).unwrap_or_default()
+    }
+
+    fn pressed_hotkey_configs(&self) -> impl Iterator<Item = &HotkeyConfig> {
+        self.pressed_hotkeys.iter().filter_map(|index| self.hotkey(*index))
+    }
+
+    // The buttons that should currently be held
+    fn held_buttons(&self) -> HeldButtons {
+        // if no hotkey is pressed, return an empty HeldButtons, so that all buttons will be released.
+        // the chords of multiple hotkeys are held together, in the order in which they were pressed
+        let mut buttons: HeldButtons = self.pressed_hotkey_configs()
+            .filter(|hotkey| hotkey.mode == HotkeyMode::Hold && hotkey.action == HotkeyAction::Button)
+            .flat_map(hotkey_buttons)
+            .collect();
+
+        buttons.extend(self.latched_buttons.iter());
+        buttons
+    }
+
+    // The gamepad buttons that should currently be held
+    fn gamepad_buttons(&self) -> HeldGamepadButtons {
+        self.pressed_hotkey_configs()
+            .filter_map(|hotkey| match hotkey.action {
+                HotkeyAction::GamepadButton(button) => Some(button),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // The buttons that should be tapped repeatedly, at a rate that follows the breath strength
+    fn repeat_tap(&self) -> Option<RepeatTap> {
+        // only one hotkey can repeat at a time, the one that was pressed first
+        let hotkey = self.pressed_hotkey_configs()
+            .find(|hotkey| hotkey.mode == HotkeyMode::Repeat && hotkey.action == HotkeyAction::Button)?;
+
+        let breath_strength = match hotkey.breath_direction {
+            BreathDirection::Puff => self.breath_value,
+            BreathDirection::Sip => -self.breath_value,
+        };
+
+        Some(RepeatTap {
+            buttons: hotkey_buttons(hotkey),
+            tap_duration: hotkey.tap_duration(),
+            interval: hotkey.repeat_interval(breath_strength),
+        })
+    }
+
+    fn set_pressed_hotkeys(&mut self, new_pressed_hotkeys: Vec<HotkeyIndex>, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        if self.pressed_hotkeys == new_pressed_hotkeys {
+            return;
+        }
+
+        for hotkey in self.pressed_hotkeys.iter()
+            .filter(|index| !new_pressed_hotkeys.contains(index))
+            .filter_map(|index| self.hotkey(*index))
+        {
+            if hotkey.mode == HotkeyMode::TapOnRelease {
+                taps.extend(hotkey_activation(hotkey));
+            }
+        }
+
+        let newly_pressed_indices: Vec<HotkeyIndex> = new_pressed_hotkeys.iter()
+            .filter(|index| !self.pressed_hotkeys.contains(index))
+            .copied()
+            .collect();
+        let newly_pressed: Vec<HotkeyConfig> = newly_pressed_indices.iter()
+            .filter_map(|index| self.hotkey(*index))
+            .cloned()
+            .collect();
+
+        self.pressed_since.retain(|(index, _)| new_pressed_hotkeys.contains(index));
+        self.pressed_since.extend(newly_pressed_indices.into_iter().map(|index| (index, now)));
+
+        for hotkey in newly_pressed {
+            match (hotkey.mode, &hotkey.action) {
+                (HotkeyMode::Latch, HotkeyAction::Button) => {
+                    self.toggle_latch(&hotkey, now);
+                },
+                (HotkeyMode::TapOnRelease, _) |
+                (HotkeyMode::Hold, HotkeyAction::Button) |
+                (HotkeyMode::Hold, HotkeyAction::Scroll { .. }) |
+                (HotkeyMode::Repeat, HotkeyAction::Button) => {},
+                _ => {
+                    // TapOnPress, or a macro or scrolling in the other modes
+                    taps.extend(hotkey_activation(&hotkey));
+                },
+            }
+
+            if hotkey.min_duration.is_some() {
+                self.long_hotkey_pressed = true;
+            }
+
+            let threshold = hotkey.threshold.unwrap_or(0);
+            self.exclusive_threshold = Some(self.exclusive_threshold.map_or(threshold, |exclusive| exclusive.max(threshold)));
+        }
+
+        self.pressed_hotkeys = new_pressed_hotkeys;
+    }
+
+    // The instant at which each pressed hotkey has been held for longer than its maximum hold time
+    fn hold_deadlines(&self) -> impl Iterator<Item = (HotkeyIndex, Duration, Instant)> + '_ {
+        self.pressed_since.iter().filter_map(|(index, since)| {
+            let hotkey = self.hotkey(*index).filter(|hotkey| holds_input(hotkey))?;
+            let max_hold_time = hotkey.max_hold_time(self.max_hold_time)?;
+            Some((*index, max_hold_time, *since + max_hold_time))
+        })
+    }
+
+    // The instant at which each latch hotkey has been latched for longer than its maximum hold time
+    fn latch_deadlines(&self) -> impl Iterator<Item = (&HotkeyConfig, Duration, Instant)> + '_ {
+        self.latched_since.iter().filter_map(|(hotkey, since)| {
+            let max_hold_time = hotkey.max_hold_time(self.max_hold_time)?;
+            Some((hotkey, max_hold_time, *since + max_hold_time))
+        })
+    }
+
+    // Release the hotkeys that have been held for longer than their maximum hold time, for example
+    // because the user fell asleep or the breath sensor has drifted. Latched buttons are unlatched
+    fn release_held_too_long(&mut self, now: Instant) {
+        let exceeded_latches: Vec<(HotkeyConfig, Duration)> = self.latch_deadlines()
+            .filter(|(_, _, deadline)| *deadline <= now)
+            .map(|(hotkey, max_hold_time, _)| (hotkey.clone(), max_hold_time))
+            .collect();
+
+        for (hotkey, max_hold_time) in exceeded_latches {
+            warn!("Unlatching {:?} because it has been latched for longer than {:?}", hotkey.buttons, max_hold_time);
+            self.unlatch(&hotkey_buttons(&hotkey));
+            self.exceeded_hold_times.push((hotkey, max_hold_time));
+        }
+
+        let exceeded: Vec<(HotkeyIndex, Duration)> = self.hold_deadlines()
+            .filter(|(_, _, deadline)| *deadline <= now)
+            .map(|(index, max_hold_time, _)| (index, max_hold_time))
+            .collect();
+
+        for (index, max_hold_time) in exceeded {
+            warn!("Releasing hotkey {:?} because it has been held for longer than {:?}", index, max_hold_time);
+            self.pressed_hotkeys.retain(|pressed| *pressed != index);
+            self.pressed_since.retain(|(pressed, _)| *pressed != index);
+            self.pending_releases.retain(|(pressed, _)| *pressed != index);
+            self.active_hotkeys.retain(|active| *active != index);
+            self.tripped_hotkeys.push(index);
+
+            if let Some(hotkey) = self.hotkey(index).cloned() {
+                self.exceeded_hold_times.push((hotkey, max_hold_time));
+            }
+        }
+    }
+
+    // The next instant at which handle_timer() should be called
+    fn next_deadline(&self) -> Option<Instant> {
+        let press_deadline = self.pending_presses.iter().map(|(_, deadline)| *deadline).min();
+        let release_deadline = self.pending_releases.iter().map(|(_, deadline)| *deadline).min();
+
+        let hold_deadline = earliest(
+            self.hold_deadlines().map(|(_, _, deadline)| deadline).min(),
+            self.latch_deadlines().map(|(_, _, deadline)| deadline).min(),
+        );
+
+        earliest(
+            earliest(
+                earliest(earliest(press_deadline, release_deadline), hold_deadline),
+                earliest(self.scroll_deadline, self.commit_deadline),
+            ),
+            earliest(
+                earliest(self.gesture_recognizer.next_deadline(), self.duration_deadline),
+                earliest(self.morse_decoder.next_deadline(), self.pointer_controller.next_deadline()),
+            ),
+        )
+    }
+
+    // Keep track of the start and end of breaths. Hotkeys with a maximum duration are activated
+    // when the breath ends.
+    fn track_breath(&mut self, breath_value: i8, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        let direction = if breath_value < 0 { BreathDirection::Sip } else { BreathDirection::Puff };
+
+        let direction_changed = self.breath_start.is_some_and(|(start_direction, _)| start_direction != direction);
+        if breath_value == 0 || direction_changed {
+            self.end_breath(now, taps);
+        }
+
+        if breath_value == 0 && !self.tripped_hotkeys.is_empty() {
+            info!("The breath has returned to neutral, hotkeys that were held for too long can be pressed again");
+            self.tripped_hotkeys.clear();
+        }
+
+        if breath_value != 0 {
+            if self.breath_start.is_none() {
+                if self.has_macros {
+                    // a new breath stops the macro that is still running
+                    taps.push(InputSimCommand::CancelMacro);
+                }
+
+                self.breath_start = Some((direction, now));
+                self.breath_peak = 0;
+                self.peak_committed = false;
+                self.long_hotkey_pressed = false;
+                self.exclusive_threshold = None;
+            }
+
+            if breath_value.abs() > self.breath_peak {
+                self.breath_peak = breath_value.abs();
+                self.breath_peak_at = Some(now);
+            }
+        }
+    }
+
+    fn end_breath(&mut self, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        let duration = self.breath_duration(now);
+
+        if let Some((direction, _)) = self.breath_start.take() {
+            // a long breath suppresses the hotkeys meant for short breaths
+            if self.long_hotkey_pressed {
+                return;
+            }
+
+            let peak = self.breath_peak;
+            let hotkeys = self.hotkeys(direction);
+            let matches = (0..hotkeys.len()).filter(|index| {
+                let hotkey = &hotkeys[*index];
+                hotkey.max_duration.is_some() &&
+                    hotkey.strength_in_range(peak) &&
+                    hotkey.duration_matches(duration)
+            });
+            let matched: Vec<HotkeyConfig> = self.select_matches(direction, matches)
+                .into_iter()
+                .map(|index| hotkeys[index].clone())
+                .collect();
+
+            if !matched.is_empty() {
+                for hotkey in matched {
+                    self.tap_hotkey(&hotkey, now, taps);
+                }
+                return;
+            }
+
+            // the breath ended before the look-ahead delay, so the hotkey of its peak has not been
+            // pressed yet
+            if self.selection_strategy(direction) == SelectionStrategy::PeakCommit && !self.peak_committed {
+                self.peak_committed = true;
+                self.commit_deadline = None;
+
+                for hotkey in self.peak_hotkeys(direction, duration) {
+                    self.tap_hotkey(&hotkey, now, taps);
+                }
+            }
+        }
+    }
+
+    // The hotkeys that match the peak of the current breath, ignoring hotkeys with a maximum duration
+    fn peak_hotkeys(&self, direction: BreathDirection, breath_duration: Duration) -> Vec<HotkeyConfig> {
+        let hotkeys = self.hotkeys(direction);
+        self.peak_hotkey_indices(direction, breath_duration)
+            .into_iter()
+            .map(|index| hotkeys[index].clone())
+            .collect()
+    }
+
+    fn peak_hotkey_indices(&self, direction: BreathDirection, breath_duration: Duration) -> Vec<usize> {
+        let peak = self.breath_peak;
+        let hotkeys = self.hotkeys(direction);
+        let matches = (0..hotkeys.len()).filter(|index| {
+            let hotkey = &hotkeys[*index];
+            hotkey.strength_in_range(peak) &&
+                hotkey.max_duration.is_none() &&
+                hotkey.duration_matches(breath_duration) &&
+                !self.tripped_hotkeys.contains(&(direction, *index))
+        });
+        self.select_matches(direction, matches)
+    }
+
+    // True if an active hotkey should remain active at the given breath strength
+    fn above_release_level(&self, (direction, index): HotkeyIndex, breath_value_abs: i8) -> bool {
+        // leaving the band through the upper threshold deactivates the hotkey
+        self.hotkeys(direction).get(index).is_some_and(|hotkey| {
+            hotkey.release_level().is_some_and(|release_level| breath_value_abs >= release_level) &&
+                hotkey.below_upper_threshold(breath_value_abs)
+        })
+    }
+
+    // Determine which hotkeys match the current breath value, and schedule the presses and releases
+    fn evaluate(&mut self, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        let breath_value = self.breath_value;
+        let direction = if breath_value < 0 { BreathDirection::Sip } else { BreathDirection::Puff };
+        let breath_duration = self.breath_duration(now);
+        let breath_start = self.breath_start.map(|(_, start)| start);
+        let hotkeys = self.hotkeys(direction);
+        let breath_value_abs = breath_value.abs();
+        let policy = self.matching_policy(direction);
+
+        // once a hotkey has been pressed, ExclusiveHighest ignores the hotkeys with a lower threshold
+        let exclusive_threshold = self.exclusive_threshold.filter(|_| policy == MatchingPolicy::ExclusiveHighest);
+        // hotkeys that were held for too long are ignored until the breath returns to neutral
+        let not_excluded = |index: usize| {
+            exclusive_threshold.is_none_or(|threshold| hotkeys[index].threshold.unwrap_or(0) >= threshold) &&
+                !self.tripped_hotkeys.contains(&(direction, index))
+        };
+
+        // hotkeys are sorted by descending threshold, so the first match is the highest threshold.
+        // hotkeys with a maximum duration are handled by end_breath()
+        let matches = (0..hotkeys.len()).filter(|index| {
+            let hotkey = &hotkeys[*index];
+            hotkey.strength_in_range(breath_value_abs) &&
+                hotkey.max_duration.is_none() &&
+                hotkey.duration_matches(breath_duration) &&
+                not_excluded(*index)
+        });
+        let mut matched = self.select_matches(direction, matches);
+
+        // the next instant at which a hotkey with a minimum duration becomes eligible
+        let duration_deadline = breath_start.and_then(|start| {
+            hotkeys.iter()
+                .filter_map(|hotkey| hotkey.min_duration())
+                .map(|min_duration| start + min_duration)
+                .filter(|deadline| *deadline > now)
+                .min()
+        });
+
+        // keep the active hotkeys until the breath drops below their release level
+        let still_active: Vec<usize> = self.active_hotkeys
+            .iter()
+            .filter(|(active_direction, index)| {
+                *active_direction == direction &&
+                    self.above_release_level((direction, *index), breath_value_abs) &&
+                    not_excluded(*index)
+            })
+            .map(|(_, index)| *index)
+            .collect();
+
+        if policy == MatchingPolicy::AllMatch {
+            for index in still_active {
+                if !matched.contains(&index) {
+                    matched.push(index);
+                }
+            }
+            matched.sort_unstable();
+        }
+        else if let Some(&active_index) = still_active.first() {
+            // unless a hotkey with a higher threshold has been reached
+            if matched.first().is_none_or(|index| *index >= active_index) {
+                matched = vec![active_index];
+            }
+        }
+
+        self.commit_deadline = None;
+        if breath_start.is_some() && self.selection_strategy(direction) == SelectionStrategy::PeakCommit {
+            matched = self.select_at_peak(direction, now, taps);
+        }
+
+        self.duration_deadline = duration_deadline;
+        self.active_hotkeys = matched.into_iter().map(|index| (direction, index)).collect();
+
+        // a pressed hotkey that is no longer active is released after its release delay, unless it
+        // becomes active again before then
+        let active_hotkeys = self.active_hotkeys.clone();
+        self.pending_releases.retain(|(index, _)| !active_hotkeys.contains(index));
+        for pressed in self.pressed_hotkeys.clone() {
+            if !active_hotkeys.contains(&pressed) && !self.pending_releases.iter().any(|(index, _)| *index == pressed) {
+                let delay = self.hotkey(pressed).map(|hotkey| hotkey.release_delay()).unwrap_or_default();
+                self.pending_releases.push((pressed, now + delay));
+            }
+        }
+
+        // an active hotkey is pressed after its activation delay, unless it is no longer active by then
+        self.pending_presses.retain(|(index, _)| active_hotkeys.contains(index));
+        for active in active_hotkeys {
+            // keep the original deadline if this hotkey was already pending
+            if !self.pressed_hotkeys.contains(&active) && !self.pending_presses.iter().any(|(index, _)| *index == active) {
+                let delay = self.hotkey(active).map(|hotkey| hotkey.activation_delay()).unwrap_or_default();
+                self.pending_presses.push((active, now + delay));
+            }
+        }
+    }
+
+    // With the PeakCommit strategy, no hotkey is pressed until the breath has stopped getting
+    // stronger. After that, only the hotkeys of the peak can remain pressed for the rest of the breath
+    fn select_at_peak(
+        &mut self,
+        direction: BreathDirection,
+        now: Instant,
+        taps: &mut Vec<InputSimCommand>,
+    ) -> Vec<usize> {
+        let breath_value_abs = self.breath_value.abs();
+
+        if self.peak_committed {
+            // keep the chosen hotkeys until the breath drops below their release level
+            return self.active_hotkeys
+                .iter()
+                .filter(|active| active.0 == direction && self.above_release_level(**active, breath_value_abs))
+                .map(|(_, index)| *index)
+                .collect();
+        }
+
+        let breath_duration = self.breath_duration(now);
+        let peak_indices = self.peak_hotkey_indices(direction, breath_duration);
+        let Some(&peak_index) = peak_indices.first() else {
+            return Vec::new();
+        };
+
+        let below_release_level = self.hotkeys(direction)[peak_index]
+            .release_level()
+            .is_none_or(|release_level| breath_value_abs < release_level);
+
+        if below_release_level {
+            // a short breath that already dropped below the hotkey of its peak
+            self.peak_committed = true;
+            for hotkey in self.peak_hotkeys(direction, breath_duration) {
+                self.tap_hotkey(&hotkey, now, taps);
+            }
+            return Vec::new();
+        }
+
+        let commit_at = self.breath_peak_at.unwrap_or(now) + self.peak_commit_delay;
+        if now >= commit_at {
+            self.peak_committed = true;
+            return peak_indices;
+        }
+
+        self.commit_deadline = Some(commit_at);
+        Vec::new()
+    }
+
+    // Scroll at a rate that follows the breath strength, for as long as a scroll hotkey is pressed
+    fn update_scroll(&mut self, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        // only one hotkey can scroll at a time, the one that was pressed first
+        let scroll = self.pressed_hotkey_configs()
+            .find_map(|hotkey| match hotkey.action {
+                HotkeyAction::Scroll { horizontal, speed } if hotkey.mode == HotkeyMode::Hold => Some((
+                    hotkey.breath_direction,
+                    hotkey.threshold.unwrap_or(0),
+                    horizontal,
+                    speed.unwrap_or(DEFAULT_SCROLL_SPEED),
+                )),
+                _ => None,
+            });
+
+        let Some((direction, threshold, horizontal, speed)) = scroll else {
+            self.scroll_deadline = None;
+            self.scroll_remainder = 0.0;
+            return;
+        };
+
+        // the first tick happens as soon as the hotkey is pressed
+        let deadline = self.scroll_deadline.unwrap_or(now);
+        if deadline > now {
+            self.scroll_deadline = Some(deadline);
+            return;
+        }
+
+        let interval = Duration::from_millis(SCROLL_TICK_INTERVAL);
+        let breath_value = match direction {
+            BreathDirection::Puff => self.breath_value,
+            BreathDirection::Sip => -self.breath_value,
+        };
+        let strength = strength_above_threshold(breath_value, threshold);
+
+        self.scroll_remainder += f64::from(speed) * strength * interval.as_secs_f64();
+        let notches = self.scroll_remainder.trunc();
+        self.scroll_remainder -= notches;
+
+        if notches >= 1.0 {
+            taps.push(scroll_command(direction, horizontal, notches as i64));
+        }
+
+        // don't try to catch up if the timer has been late
+        let next_deadline = deadline + interval;
+        self.scroll_deadline = Some(if next_deadline > now { next_deadline } else { now + interval });
+    }
+
+    fn handle_timer(&mut self, now: Instant) -> Vec<InputSimCommand> {
+        self.update(now, Vec::new())
+    }
+
+    fn gestures_enabled(&self) -> bool {
+        !self.gestures.is_empty() && !self.morse_enabled && !self.pointer_enabled
+    }
+
+    // True while the breaths since the last gesture might still become a gesture
+    fn gesture_pending(&self) -> bool {
+        let directions: Vec<BreathDirection> = self.gesture_recognizer.directions().collect();
+        !directions.is_empty() && self.gestures.iter().any(|gesture| gesture.breaths.starts_with(&directions))
+    }
+
+    // Tap the hotkeys that a short breath activates. Used for breaths that were kept from the
+    // hotkeys because they might have been part of a gesture
+    fn replay_breath(&mut self, breath: GestureBreath, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        let hotkeys = self.hotkeys(breath.direction);
+        let in_range = |hotkey: &HotkeyConfig| hotkey.strength_in_range(breath.peak) && hotkey.duration_matches(breath.duration);
+
+        let mut matched = self.select_matches(
+            breath.direction,
+            (0..hotkeys.len()).filter(|index| hotkeys[*index].max_duration.is_some() && in_range(&hotkeys[*index])),
+        );
+        if matched.is_empty() {
+            matched = self.select_matches(
+                breath.direction,
+                (0..hotkeys.len()).filter(|index| hotkeys[*index].max_duration.is_none() && in_range(&hotkeys[*index])),
+            );
+        }
+
+        let matched: Vec<HotkeyConfig> = matched.into_iter().map(|index| hotkeys[index].clone()).collect();
+        for hotkey in matched {
+            self.tap_hotkey(&hotkey, now, taps);
+        }
+    }
+
+    fn complete_gesture(&mut self, sequence: Vec<GestureBreath>, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        let directions: Vec<BreathDirection> = sequence.iter().map(|breath| breath.direction).collect();
+
+        match self.gestures.iter().find(|gesture| gesture.breaths == directions) {
+            Some(gesture) => {
+                info!("Recognized gesture {:?}", directions);
+                taps.push(InputSimCommand::Tap(gesture_buttons(gesture), Duration::from_millis(u64::from(DEFAULT_TAP_DURATION))));
+            },
+            None => {
+                debug!("No gesture matches {:?}", directions);
+                for breath in sequence {
+                    self.replay_breath(breath, now, taps);
+                }
+            },
+        }
+    }
+
+    // Recognize gestures. The hotkeys see a neutral breath while the breath might be part of a
+    // gesture, the breaths that do not become a gesture are tapped afterwards
+    fn update_gestures(&mut self, now: Instant, taps: &mut Vec<InputSimCommand>) {
+        if let Some(sequence) = self.gesture_recognizer.handle_timer(now) {
+            self.complete_gesture(sequence, now, taps);
+        }
+
+        if !self.gesture_pending() {
+            // the breaths so far can no longer become a gesture
+            for breath in self.gesture_recognizer.take_sequence() {
+                self.replay_breath(breath, now, taps);
+            }
+
+            // the breath in progress can not start a gesture either, it is left to the hotkeys
+            if !self.gesture_pending() {
+                self.gesture_recognizer.ignore_current_breath();
+            }
+        }
+
+        let breath_value = if self.gesture_pending() { 0 } else { self.latest_breath_value };
+        if breath_value != self.breath_value {
+            self.track_breath(breath_value, now, taps);
+            self.breath_value = breath_value;
+        }
+    }
+
+    // Apply the pending press and release if their deadline has passed.
+    // Returns the commands that should be sent to input_sim
+    fn update(&mut self, now: Instant, mut taps: Vec<InputSimCommand>) -> Vec<InputSimCommand> {
+        if self.gestures_enabled() {
+            self.update_gestures(now, &mut taps);
+        }
+
+        self.evaluate(now, &mut taps);
+
+        let due_presses: Vec<HotkeyIndex> = self.pending_presses
+            .iter()
+            .filter(|(_, deadline)| *deadline <= now)
+            .map(|(index, _)| *index)
+            .collect();
+
+        if let Some(&(direction, _)) = due_presses.first() {
+            // with AllMatch, the new hotkeys are held together with the hotkeys that are already
+            // pressed. otherwise they replace them
+            let mut pressed = self.pressed_hotkeys.clone();
+            match self.matching_policy(direction) {
+                MatchingPolicy::AllMatch => pressed.retain(|(pressed_direction, _)| *pressed_direction == direction),
+                MatchingPolicy::FirstMatch | MatchingPolicy::ExclusiveHighest => pressed.clear(),
+            }
+            pressed.extend(due_presses);
+
+            self.pending_presses.retain(|(_, deadline)| *deadline > now);
+            self.pending_releases.retain(|(index, _)| pressed.contains(index));
+            self.set_pressed_hotkeys(pressed, now, &mut taps);
+        }
+
+        let due_releases: Vec<HotkeyIndex> = self.pending_releases
+            .iter()
+            .filter(|(_, deadline)| *deadline <= now)
+            .map(|(index, _)| *index)
+            .collect();
+
+        if !due_releases.is_empty() {
+            let mut pressed = self.pressed_hotkeys.clone();
+            pressed.retain(|index| !due_releases.contains(index));
+
+            self.pending_releases.retain(|(_, deadline)| *deadline > now);
+            self.set_pressed_hotkeys(pressed, now, &mut taps);
+        }
+
+        self.release_held_too_long(now);
+        self.update_scroll(now, &mut taps);
+
+        if let Some(button) = self.morse_decoder.handle_timer(now) {
+            taps.push(morse_tap(button));
+        }
+
+        self.pointer_controller.handle_timer(now);
+
+        // release held buttons before tapping, in case a tap hotkey replaces a hold hotkey
+        let mut commands = vec![
+            InputSimCommand::SetHeldButtons(self.held_buttons()),
+            InputSimCommand::SetRepeat(self.repeat_tap()),
+        ];
+        commands.append(&mut taps);
+        commands
+    }
+
+    // Returns the commands that should be sent to input_sim for the given breath value
+    fn handle_breath(&mut self, breath_value: i8, now: Instant) -> Vec<InputSimCommand> {
+        if self.morse_enabled {
+            let taps = self.morse_decoder.handle_breath(breath_value, now)
+                .map(morse_tap)
+                .into_iter()
+                .collect();
+            return self.update(now, taps);
+        }
+
+        if self.pointer_enabled {
+            self.pointer_controller.handle_breath(breath_value, now);
+            let velocity = InputSimCommand::SetPointerVelocity(self.pointer_controller.velocity());
+            return self.update(now, vec![velocity]);
+        }
+
+        if self.gestures_enabled() {
+            self.latest_breath_value = breath_value;
+            self.gesture_recognizer.handle_breath(breath_value, now);
+            // update_gestures() passes the breath on to the hotkeys
+            return self.update(now, Vec::new());
+        }
+
+        let mut taps = Vec::new();
+        self.track_breath(breath_value, now, &mut taps);
+        self.breath_value = breath_value;
+        self.update(now, taps)
+    }
+}
+
+// Report an event to the GUI without waiting, so that a GUI that does not keep up never delays the
+// input. Returns false if the channel is full, in which case the event should be reported again
+// later. If the GUI has gone away, the event is dropped
+fn report(status_sender: &mut Sender<BreathInputSimEvent>, event: BreathInputSimEvent) -> bool {
+    match status_sender.try_send(event) {
+        Ok(()) => true,
+        Err(err) => !err.is_full(),
+    }
+}
+
+pub fn breath_input_sim(cancel: CancellationToken, backend: Arc<dyn InputBackend>) -> (Sender<DeviceEvent>, Sender<BreathInputSimCommand>, Receiver<BreathInputSimEvent>, JoinHandle<()>) {
     let (event_sender, mut event_receiver) = channel::<DeviceEvent>(128);
     let (command_sender, mut command_receiver) = channel::<BreathInputSimCommand>(8);
-    let (mut input_sim_tx, input_sim_handle) = input_sim_task(cancel.clone());
+    let (mut status_sender, status_receiver) = channel::<BreathInputSimEvent>(64);
+    let (mut input_sim_tx, input_sim_handle) = input_sim_task(cancel.clone(), backend);
 
     let handle = spawn(async move {
-        let mut puff_hotkeys: Vec<HotkeyConfig> = Vec::new();
-        let mut sip_hotkeys: Vec<HotkeyConfig> = Vec::new();
+        let mut state = BreathInputSimState::new();
+        let mut gamepad = GamepadOutput::new();
+        let mut filter = BreathFilter::new(FilterConfig::default());
+        let mut artifact_detector = ArtifactDetector::new(&ArtifactConfig::default());
+        // the breath value, latched buttons, morse code and pointer direction as last reported to
+        // status_sender
+        let mut breath_value: Option<i8> = None;
+        let mut reported_breath_value: Option<i8> = None;
+        let mut latched_buttons: HeldButtons = IndexSet::new();
+        let mut morse_code = String::new();
+        let mut pointer_direction = None;
+        let mut rejected_artifacts = 0;
+        // the error that has not been reported yet
+        let mut gamepad_error: Option<String> = None;
 
         'mainloop: loop {
+            let deadline = state.next_deadline();
+            let filter_deadline = filter.next_deadline();
+            let artifact_deadline = artifact_detector.next_deadline();
+            // the breath value that has come out of the filter during this iteration
+            let mut filtered_breath = None;
+            // the breath value that has come out of the artifact detector during this iteration
+            let mut accepted_breath = None;
+
             tokio::select! {
                 _ = cancel.cancelled() => {
                     break 'mainloop;
                 },
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    for command in state.handle_timer(Instant::now()) {
+                        input_sim_tx.send(command)
+                            .await
+                            .unwrap_or_else(|err| panic!("Failed to send command to input_sim: {:?}", err));
+                    }
+                },
+                _ = sleep_until(filter_deadline.unwrap_or_else(Instant::now)), if filter_deadline.is_some() => {
+                    filtered_breath = filter.handle_timer(Instant::now());
+                },
+                _ = sleep_until(artifact_deadline.unwrap_or_else(Instant::now)), if artifact_deadline.is_some() => {
+                    accepted_breath = artifact_detector.handle_timer(Instant::now());
+                },
                 Some(event) = event_receiver.next() => {
-                    if let DeviceEvent::Breath(breath_value) = event {
-                        let hotkeys = if breath_value < 0 { &sip_hotkeys } else { & puff_hotkeys };
-
-                        let breath_value_abs = breath_value.abs();
-
-                        let hotkey = hotkeys.into_iter().find(|hotkey| {
-                            if let Some(threshold) = hotkey.threshold {
-                                if breath_value_abs >= threshold {
-                                    return true;
-                                }
+                    match event {
+                        DeviceEvent::StateChange(device_state) => {
+                            if device_state != DeviceState::Connected {
+                                // the device has been disconnected, make sure nothing remains pressed
+                                state.reset();
+                                filter.reset();
+                                artifact_detector.reset();
+                                gamepad.handle_breath(0);
+                                input_sim_tx.send(InputSimCommand::SetHeldButtons(IndexSet::new()))
+                                    .await
+                                    .unwrap_or_else(|err| panic!("Failed to send command to input_sim: {:?}", err));
+                                input_sim_tx.send(InputSimCommand::SetRepeat(None))
+                                    .await
+                                    .unwrap_or_else(|err| panic!("Failed to send command to input_sim: {:?}", err));
+                                input_sim_tx.send(InputSimCommand::SetPointerVelocity((0.0, 0.0)))
+                                    .await
+                                    .unwrap_or_else(|err| panic!("Failed to send command to input_sim: {:?}", err));
                             }
-                            false
-                        });
-
-                        let mut buttons: HeldButtons = IndexSet::new();
-
-                        if let Some(hotkey) = hotkey {
-                            if hotkey.modifier_shift { buttons.insert(Button::ShiftLeft); }
-                            if hotkey.modifier_ctrl { buttons.insert(Button::ControlLeft); }
-                            if hotkey.modifier_meta { buttons.insert(Button::MetaLeft); }
-                            if hotkey.modifier_alt { buttons.insert(Button::Alt); }
-                            buttons.insert(hotkey.button);
-                        }
-                        // if no hotkey matched, send an empty HeldButtons, so that all buttons will be released
-
-                        input_sim_tx.send(InputSimCommand::SetHeldButtons(buttons))
-                            .await
-                            .expect("Failed to send command to input_sim: {:?}");
+                        },
+                        DeviceEvent::Breath(breath_value) => {
+                            filtered_breath = filter.handle_breath(breath_value, Instant::now());
+                        },
                     }
                 },
                 Some(command) = command_receiver.next() => {
                     match command {
                         BreathInputSimCommand::SetConfig(new_config) => {
-                            let mut hotkeys = new_config.hotkeys;
-                            // sort descending by threshold, None last
-                            hotkeys.sort_by(
-                                |a, b|
-                                b.threshold.unwrap_or(0).cmp(&a.threshold.unwrap_or(0))
-                            );
-
-                            (puff_hotkeys, sip_hotkeys) = hotkeys
-                                .into_iter()
-                                .partition(|hotkey| hotkey.breath_direction == BreathDirection::Puff);
+                            if let Err(err) = gamepad.set_config(new_config.gamepad.clone()) {
+                                warn!("Failed to create virtual gamepad: {}", err);
+                                gamepad_error = Some(err.to_string());
+                            }
+
+                            filter.set_config(new_config.filter.clone());
+                            artifact_detector.set_config(&new_config.artifacts);
+
+                            if state.set_config(new_config) {
+                                // release what the state no longer holds. the pointer might have been
+                                // moving in a mode that is no longer enabled
+                                let commands = [
+                                    InputSimCommand::SetHeldButtons(state.held_buttons()),
+                                    InputSimCommand::SetRepeat(state.repeat_tap()),
+                                    InputSimCommand::SetPointerVelocity((0.0, 0.0)),
+                                ];
+                                for command in commands {
+                                    input_sim_tx.send(command)
+                                        .await
+                                        .unwrap_or_else(|err| panic!("Failed to send command to input_sim: {:?}", err));
+                                }
+                            }
                         },
                     }
                 },
             }
+
+            if let Some(breath_value) = filtered_breath {
+                accepted_breath = artifact_detector.handle_breath(breath_value, Instant::now());
+            }
+
+            if let Some(accepted_breath) = accepted_breath {
+                gamepad.handle_breath(accepted_breath);
+
+                for command in state.handle_breath(accepted_breath, Instant::now()) {
+                    input_sim_tx.send(command)
+                        .await
+                        .unwrap_or_else(|err| panic!("Failed to send command to input_sim: {:?}", err));
+                }
+
+                breath_value = Some(accepted_breath);
+            }
+
+            gamepad.set_buttons(state.gamepad_buttons());
+
+            // the GUI only displays these, values that could not be reported are reported again
+            // during the next iteration
+            if let Some(value) = breath_value.filter(|value| reported_breath_value != Some(*value)) {
+                if report(&mut status_sender, BreathInputSimEvent::FilteredBreath(value)) {
+                    reported_breath_value = Some(value);
+                }
+            }
+
+            if artifact_detector.rejected() != rejected_artifacts &&
+                report(&mut status_sender, BreathInputSimEvent::RejectedArtifacts(artifact_detector.rejected()))
+            {
+                rejected_artifacts = artifact_detector.rejected();
+            }
+
+            if state.latched_buttons != latched_buttons &&
+                report(&mut status_sender, BreathInputSimEvent::LatchedButtons(state.latched_buttons.clone()))
+            {
+                latched_buttons = state.latched_buttons.clone();
+            }
+
+            if let Some(message) = gamepad_error.take() {
+                if !report(&mut status_sender, BreathInputSimEvent::GamepadError(message.clone())) {
+                    gamepad_error = Some(message);
+                }
+            }
+
+            while let Some((hotkey, max_hold_time)) = state.exceeded_hold_times.first().cloned() {
+                if !report(&mut status_sender, BreathInputSimEvent::HoldTimeExceeded(hotkey, max_hold_time)) {
+                    break;
+                }
+                state.exceeded_hold_times.remove(0);
+            }
+
+            if state.morse_decoder.code() != morse_code &&
+                report(&mut status_sender, BreathInputSimEvent::MorseCode(state.morse_decoder.code().to_string()))
+            {
+                morse_code = state.morse_decoder.code().to_string();
+            }
+
+            if pointer_direction != Some(state.pointer_controller.direction()) &&
+                report(&mut status_sender, BreathInputSimEvent::PointerDirection(state.pointer_controller.direction()))
+            {
+                pointer_direction = Some(state.pointer_controller.direction());
+            }
         }
 
         input_sim_handle.await.expect("Failed to join input_sim_task");
@@ -79,5 +1222,535 @@ pub fn breath_input_sim(cancel: CancellationToken) -> (Sender<DeviceEvent>, Send
         ()
     });
 
-    return (event_sender, command_sender, handle);
-}
\ No newline at end of file
+    return (event_sender, command_sender, status_receiver, handle);
+}
+
+/**
+ * Forwards the events from the receiver that was returned by breath_input_sim(). The receiver is
+ * taken out of the mutex when the subscription starts.
+ */
+pub fn breath_input_sim_subscription(receiver: Arc<Mutex<Option<Receiver<BreathInputSimEvent>>>>) -> Subscription<BreathInputSimEvent> {
+    struct BreathInputSimEvents;
+
+    subscription::channel(
+        std::any::TypeId::of::<BreathInputSimEvents>(),
+        64,
+        move |mut subscription_sender| async move {
+            let receiver = receiver.lock().expect("Failed to lock breath_input_sim receiver").take();
+
+            if let Some(mut receiver) = receiver {
+                while let Some(event) = receiver.next().await {
+                    subscription_sender.send(event).await.expect("Failed to send BreathInputSimEvent");
+                }
+            }
+
+            // note: subscription::channel expects the future to never resolve
+            pending().await
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::{advance, sleep};
+    use crate::sim::backend::{InputEvent, RecordingBackend};
+    use super::*;
+
+    fn hold_hotkey(breath_direction: BreathDirection, threshold: i8, button: Button) -> HotkeyConfig {
+        HotkeyConfig {
+            breath_direction,
+            threshold: Some(threshold),
+            upper_threshold: None,
+            release_threshold: None,
+            activation_delay: None,
+            release_delay: None,
+            mode: HotkeyMode::Hold,
+            tap_duration: None,
+            min_repeat_rate: None,
+            max_repeat_rate: None,
+            min_duration: None,
+            max_duration: None,
+            max_hold_time: None,
+            buttons: vec![button],
+            action: HotkeyAction::Button,
+        }
+    }
+
+    // A state with a single puff hotkey that holds MouseLeft, with the given delays in milliseconds
+    fn delayed_state(activation_delay: u32, release_delay: u32) -> BreathInputSimState {
+        let mut hotkey = hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft);
+        hotkey.activation_delay = Some(activation_delay);
+        hotkey.release_delay = Some(release_delay);
+
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config { hotkeys: vec![hotkey], ..Config::default() });
+        state
+    }
+
+    fn held(state: &BreathInputSimState) -> Vec<Button> {
+        state.held_buttons().into_iter().collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn press_waits_for_activation_delay() {
+        let mut state = delayed_state(100, 0);
+        let start = Instant::now();
+
+        state.handle_breath(50, start);
+        assert!(held(&state).is_empty());
+        assert_eq!(state.next_deadline(), Some(start + Duration::from_millis(100)));
+
+        advance(Duration::from_millis(99)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+
+        advance(Duration::from_millis(1)).await;
+        state.handle_timer(Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn breath_shorter_than_activation_delay_is_ignored() {
+        let mut state = delayed_state(100, 0);
+
+        state.handle_breath(50, Instant::now());
+        advance(Duration::from_millis(99)).await;
+        state.handle_breath(0, Instant::now());
+        assert_eq!(state.next_deadline(), None);
+
+        advance(Duration::from_millis(1)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn activation_delay_restarts_with_each_breath() {
+        let mut state = delayed_state(100, 0);
+
+        state.handle_breath(50, Instant::now());
+        advance(Duration::from_millis(60)).await;
+        state.handle_breath(0, Instant::now());
+        advance(Duration::from_millis(10)).await;
+        state.handle_breath(50, Instant::now());
+
+        advance(Duration::from_millis(60)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+
+        advance(Duration::from_millis(40)).await;
+        state.handle_timer(Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn release_waits_for_grace_period() {
+        let mut state = delayed_state(0, 150);
+
+        state.handle_breath(50, Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+
+        let released_at = Instant::now();
+        state.handle_breath(0, released_at);
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+        assert_eq!(state.next_deadline(), Some(released_at + Duration::from_millis(150)));
+
+        advance(Duration::from_millis(149)).await;
+        state.handle_timer(Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+
+        advance(Duration::from_millis(1)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dip_within_grace_period_keeps_hotkey_pressed() {
+        let mut state = delayed_state(0, 150);
+
+        state.handle_breath(50, Instant::now());
+        advance(Duration::from_millis(500)).await;
+        state.handle_breath(5, Instant::now());
+        advance(Duration::from_millis(149)).await;
+        state.handle_breath(50, Instant::now());
+        assert_eq!(state.next_deadline(), None);
+
+        advance(Duration::from_millis(1000)).await;
+        state.handle_timer(Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pending_press_is_cancelled_by_stronger_hotkey() {
+        let mut weak = hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft);
+        weak.activation_delay = Some(100);
+        let mut strong = hold_hotkey(BreathDirection::Puff, 60, Button::MouseRight);
+        strong.activation_delay = Some(100);
+
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config { hotkeys: vec![weak, strong], ..Config::default() });
+
+        state.handle_breath(30, Instant::now());
+        advance(Duration::from_millis(50)).await;
+        state.handle_breath(80, Instant::now());
+
+        advance(Duration::from_millis(50)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+
+        advance(Duration::from_millis(50)).await;
+        state.handle_timer(Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseRight]);
+    }
+
+    struct Recording {
+        backend: Arc<RecordingBackend>,
+        event_sender: Sender<DeviceEvent>,
+        command_sender: Sender<BreathInputSimCommand>,
+        cancel: CancellationToken,
+        handle: JoinHandle<()>,
+    }
+
+    impl Recording {
+        // Run breath_input_sim with the given hotkeys, recording its input
+        async fn start(hotkeys: Vec<HotkeyConfig>) -> Self {
+            let backend = Arc::new(RecordingBackend::new());
+            let cancel = CancellationToken::new();
+            // nobody receives the status events, which must not stop breath_input_sim
+            let (event_sender, command_sender, _, handle) = breath_input_sim(cancel.clone(), backend.clone());
+
+            let mut recording = Recording { backend, event_sender, command_sender, cancel, handle };
+            recording.set_config(Config { hotkeys, ..Config::default() }).await;
+            recording
+        }
+
+        async fn set_config(&mut self, config: Config) {
+            self.command_sender.send(BreathInputSimCommand::SetConfig(config)).await.unwrap();
+            // let the config be applied before the next breath, it might reset the state
+            sleep(Duration::from_millis(1)).await;
+        }
+
+        async fn breathe(&mut self, breath_value: i8) {
+            self.event_sender.send(DeviceEvent::Breath(breath_value)).await.unwrap();
+        }
+
+        fn events(&self) -> Vec<InputEvent> {
+            self.backend.events().into_iter().map(|recorded| recorded.event).collect()
+        }
+
+        async fn stop(self) -> Vec<InputEvent> {
+            self.cancel.cancel();
+            self.handle.await.unwrap();
+            self.backend.events().into_iter().map(|recorded| recorded.event).collect()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recorded_hold_follows_delays() {
+        let mut hotkey = hold_hotkey(BreathDirection::Puff, 10, Button::KeyA);
+        hotkey.activation_delay = Some(100);
+        hotkey.release_delay = Some(200);
+        let mut recording = Recording::start(vec![hotkey]).await;
+
+        recording.breathe(50).await;
+        sleep(Duration::from_millis(90)).await;
+        assert!(recording.events().is_empty());
+
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(recording.events(), vec![InputEvent::Press(Button::KeyA)]);
+
+        recording.breathe(0).await;
+        sleep(Duration::from_millis(190)).await;
+        assert_eq!(recording.events(), vec![InputEvent::Press(Button::KeyA)]);
+
+        sleep(Duration::from_millis(20)).await;
+        assert_eq!(recording.stop().await, vec![InputEvent::Press(Button::KeyA), InputEvent::Release(Button::KeyA)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recorded_chord_is_released_in_reverse_order() {
+        let mut hotkey = hold_hotkey(BreathDirection::Sip, 10, Button::ControlLeft);
+        hotkey.buttons.push(Button::KeyC);
+        let mut recording = Recording::start(vec![hotkey]).await;
+
+        recording.breathe(-50).await;
+        sleep(Duration::from_millis(10)).await;
+        recording.breathe(0).await;
+        sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(recording.stop().await, vec![
+            InputEvent::Press(Button::ControlLeft),
+            InputEvent::Press(Button::KeyC),
+            InputEvent::Release(Button::KeyC),
+            InputEvent::Release(Button::ControlLeft),
+        ]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn held_buttons_are_released_when_stopped() {
+        let mut recording = Recording::start(vec![hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft)]).await;
+
+        recording.breathe(50).await;
+        sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(recording.stop().await, vec![InputEvent::Press(Button::MouseLeft), InputEvent::Release(Button::MouseLeft)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn max_hold_time_starts_when_each_hotkey_is_pressed() {
+        let weak = hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft);
+        let strong = hold_hotkey(BreathDirection::Puff, 60, Button::MouseRight);
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config { hotkeys: vec![weak, strong], max_hold_time: Some(2), ..Config::default() });
+
+        state.handle_breath(30, Instant::now());
+        advance(Duration::from_millis(1500)).await;
+        state.handle_breath(80, Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseRight]);
+
+        advance(Duration::from_millis(1999)).await;
+        state.handle_timer(Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseRight]);
+
+        advance(Duration::from_millis(1)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+        assert_eq!(state.exceeded_hold_times.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latch_is_released_after_max_hold_time() {
+        let mut latch = hold_hotkey(BreathDirection::Puff, 10, Button::ShiftLeft);
+        latch.mode = HotkeyMode::Latch;
+        latch.max_hold_time = Some(5);
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config { hotkeys: vec![latch.clone()], max_hold_time: Some(1), ..Config::default() });
+
+        state.handle_breath(50, Instant::now());
+        state.handle_breath(0, Instant::now());
+        assert_eq!(held(&state), vec![Button::ShiftLeft]);
+
+        advance(Duration::from_millis(4999)).await;
+        state.handle_timer(Instant::now());
+        assert_eq!(held(&state), vec![Button::ShiftLeft]);
+
+        advance(Duration::from_millis(1)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+        assert!(state.latched_buttons.is_empty());
+        assert_eq!(state.exceeded_hold_times, vec![(latch, Duration::from_secs(5))]);
+        assert_eq!(state.next_deadline(), None);
+    }
+
+    // The buttons of the taps among the given commands
+    fn tapped(commands: Vec<InputSimCommand>) -> Vec<Vec<Button>> {
+        commands.into_iter()
+            .filter_map(|command| match command {
+                InputSimCommand::Tap(buttons, _) => Some(buttons.into_iter().collect()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // A state with a puff hotkey that holds MouseLeft, and a double puff gesture that taps KeyG
+    fn gesture_state() -> BreathInputSimState {
+        let gesture = GestureConfig {
+            breaths: vec![BreathDirection::Puff, BreathDirection::Puff],
+            buttons: vec![Button::KeyG],
+        };
+
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config {
+            hotkeys: vec![hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft)],
+            gestures: vec![gesture],
+            gesture_window: Some(500),
+            ..Config::default()
+        });
+        state
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gesture_breaths_do_not_press_hotkeys() {
+        let mut state = gesture_state();
+
+        for _ in 0..2 {
+            state.handle_breath(50, Instant::now());
+            assert!(held(&state).is_empty());
+            advance(Duration::from_millis(100)).await;
+            state.handle_breath(0, Instant::now());
+            assert!(held(&state).is_empty());
+            advance(Duration::from_millis(100)).await;
+        }
+
+        advance(Duration::from_millis(399)).await;
+        assert!(tapped(state.handle_timer(Instant::now())).is_empty());
+
+        advance(Duration::from_millis(1)).await;
+        assert_eq!(tapped(state.handle_timer(Instant::now())), vec![vec![Button::KeyG]]);
+        assert!(held(&state).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn breath_that_does_not_become_gesture_is_tapped() {
+        let mut state = gesture_state();
+
+        state.handle_breath(50, Instant::now());
+        advance(Duration::from_millis(100)).await;
+        state.handle_breath(0, Instant::now());
+
+        advance(Duration::from_millis(500)).await;
+        assert_eq!(tapped(state.handle_timer(Instant::now())), vec![vec![Button::MouseLeft]]);
+        assert!(held(&state).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sustained_breath_is_passed_to_hotkeys() {
+        let mut state = gesture_state();
+
+        state.handle_breath(50, Instant::now());
+        advance(Duration::from_millis(499)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+
+        advance(Duration::from_millis(1)).await;
+        assert!(tapped(state.handle_timer(Instant::now())).is_empty());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+
+        state.handle_breath(0, Instant::now());
+        assert!(held(&state).is_empty());
+        advance(Duration::from_millis(1000)).await;
+        assert!(tapped(state.handle_timer(Instant::now())).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unrelated_config_change_keeps_hotkey_pressed() {
+        let hotkeys = vec![hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft)];
+        let mut recording = Recording::start(hotkeys.clone()).await;
+
+        recording.breathe(50).await;
+        sleep(Duration::from_millis(10)).await;
+
+        let mut config = Config { hotkeys, ..Config::default() };
+        config.filter.spike_filter = !config.filter.spike_filter;
+        config.calibration.auto_zero = true;
+        recording.set_config(config).await;
+
+        assert_eq!(recording.stop().await, vec![InputEvent::Press(Button::MouseLeft), InputEvent::Release(Button::MouseLeft)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn changed_hotkeys_release_pressed_buttons() {
+        let mut recording = Recording::start(vec![hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft)]).await;
+
+        recording.breathe(50).await;
+        sleep(Duration::from_millis(10)).await;
+
+        let hotkeys = vec![hold_hotkey(BreathDirection::Puff, 10, Button::MouseRight)];
+        recording.set_config(Config { hotkeys, ..Config::default() }).await;
+        assert_eq!(recording.events(), vec![InputEvent::Press(Button::MouseLeft), InputEvent::Release(Button::MouseLeft)]);
+
+        recording.breathe(51).await;
+        sleep(Duration::from_millis(10)).await;
+        assert_eq!(recording.events().last(), Some(&InputEvent::Press(Button::MouseRight)));
+        recording.stop().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn changed_hotkeys_stop_repeating() {
+        let mut repeat = hold_hotkey(BreathDirection::Puff, 10, Button::KeyR);
+        repeat.mode = HotkeyMode::Repeat;
+        let mut recording = Recording::start(vec![repeat]).await;
+
+        recording.breathe(50).await;
+        sleep(Duration::from_millis(500)).await;
+        assert!(recording.events().contains(&InputEvent::Press(Button::KeyR)));
+
+        recording.set_config(Config { hotkeys: Vec::new(), ..Config::default() }).await;
+        let taps = recording.events().len();
+        sleep(Duration::from_millis(2000)).await;
+        assert_eq!(recording.stop().await.len(), taps);
+    }
+
+    fn latch_hotkey(breath_direction: BreathDirection, button: Button) -> HotkeyConfig {
+        let mut hotkey = hold_hotkey(breath_direction, 10, button);
+        hotkey.mode = HotkeyMode::Latch;
+        hotkey
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latch_is_kept_when_hotkeys_change() {
+        let latch = latch_hotkey(BreathDirection::Puff, Button::MouseLeft);
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config { hotkeys: vec![latch.clone()], ..Config::default() });
+
+        state.handle_breath(50, Instant::now());
+        state.handle_breath(0, Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+
+        let mut edited_latch = latch.clone();
+        edited_latch.threshold = Some(30);
+        let sip = hold_hotkey(BreathDirection::Sip, 10, Button::MouseRight);
+        assert!(state.set_config(Config { hotkeys: vec![edited_latch, sip], ..Config::default() }));
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+
+        // the edited hotkey still unlatches
+        state.handle_breath(50, Instant::now());
+        state.handle_breath(0, Instant::now());
+        assert!(held(&state).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latch_is_released_when_its_hotkey_is_removed() {
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config { hotkeys: vec![latch_hotkey(BreathDirection::Puff, Button::MouseLeft)], ..Config::default() });
+
+        state.handle_breath(50, Instant::now());
+        state.handle_breath(0, Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+
+        state.set_config(Config { hotkeys: vec![latch_hotkey(BreathDirection::Puff, Button::MouseRight)], ..Config::default() });
+        assert!(held(&state).is_empty());
+        assert!(state.latched_since.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn latch_is_kept_when_filter_changes() {
+        let hotkeys = vec![latch_hotkey(BreathDirection::Puff, Button::MouseLeft)];
+        let mut recording = Recording::start(hotkeys.clone()).await;
+
+        recording.breathe(50).await;
+        sleep(Duration::from_millis(10)).await;
+        recording.breathe(0).await;
+        sleep(Duration::from_millis(10)).await;
+
+        let mut config = Config { hotkeys, ..Config::default() };
+        config.filter.spike_filter = !config.filter.spike_filter;
+        recording.set_config(config).await;
+
+        assert_eq!(recording.events(), vec![InputEvent::Press(Button::MouseLeft)]);
+        recording.stop().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tripped_hotkey_stays_released_after_config_change() {
+        let hotkey = hold_hotkey(BreathDirection::Puff, 10, Button::MouseLeft);
+        let mut state = BreathInputSimState::new();
+        state.set_config(Config { hotkeys: vec![hotkey.clone()], max_hold_time: Some(1), ..Config::default() });
+
+        state.handle_breath(50, Instant::now());
+        advance(Duration::from_secs(1)).await;
+        state.handle_timer(Instant::now());
+        assert!(held(&state).is_empty());
+
+        let mut edited = hotkey.clone();
+        edited.max_hold_time = Some(10);
+        let sip = hold_hotkey(BreathDirection::Sip, 10, Button::MouseRight);
+        assert!(state.set_config(Config { hotkeys: vec![sip, edited], max_hold_time: Some(1), ..Config::default() }));
+
+        state.handle_breath(51, Instant::now());
+        assert!(held(&state).is_empty());
+
+        state.handle_breath(0, Instant::now());
+        state.handle_breath(50, Instant::now());
+        assert_eq!(held(&state), vec![Button::MouseLeft]);
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::{Mutex, Once};
+use std::thread;
+use log::{info, warn};
+use rdev::{listen, EventType};
+
+/**
+ * The last known position of the mouse pointer. rdev can only move the pointer to an absolute
+ * position, so relative movement has to start from here.
+ */
+static CURSOR_POSITION: Mutex<Option<(f64, f64)>> = Mutex::new(None);
+
+static CURSOR_TRACKING: Once = Once::new();
+
+pub fn cursor_position() -> Option<(f64, f64)> {
+    *CURSOR_POSITION.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub fn set_cursor_position(position: (f64, f64)) {
+    *CURSOR_POSITION.lock().unwrap_or_else(|err| err.into_inner()) = Some(position);
+}
+
+/**
+ * Keep track of the position of the mouse pointer, also when it is moved by a real mouse. The
+ * listener runs on its own thread because rdev::listen() blocks forever.
+ */
+pub fn start_cursor_tracking() {
+    CURSOR_TRACKING.call_once(|| {
+        info!("Tracking the position of the mouse pointer");
+
+        let result = thread::Builder::new()
+            .name("cursor-tracking".to_string())
+            .spawn(|| {
+                let result = listen(|event| {
+                    if let EventType::MouseMove { x, y } = event.event_type {
+                        set_cursor_position((x, y));
+                    }
+                });
+
+                if let Err(err) = result {
+                    warn!("Unable to track the position of the mouse pointer: {:?}", err);
+                }
+            });
+
+        if let Err(err) = result {
+            warn!("Failed to start the cursor tracking thread: {:?}", err);
+        }
+    });
+}
@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::types::{BreathDirection, FilterConfig, Smoothing};
+use crate::device::constants::POLL_DELAY;
+
+/**
+ * The number of samples that the spike filter takes the median of.
+ */
+const SPIKE_FILTER_SAMPLES: usize = 3;
+
+fn median(samples: &VecDeque<f64>) -> f64 {
+    let mut sorted: Vec<f64> = samples.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    sorted[sorted.len() / 2]
+}
+
+// Push a sample, dropping the oldest samples so that at most `capacity` remain
+fn push_sample(samples: &mut VecDeque<f64>, sample: f64, capacity: usize) {
+    samples.push_back(sample);
+    while samples.len() > capacity {
+        samples.pop_front();
+    }
+}
+
+/**
+ * Filters the breath values of the device: a spike filter, smoothing, a neutral deadzone, and a
+ * sensitivity and response curve for puffs and sips separately.
+ * The device only reports a breath value when it changes, so while the filter has not settled,
+ * the latest value is sampled again at the rate at which the device is polled.
+ */
+pub struct BreathFilter {
+    config: FilterConfig,
+    // the most recent breath value of the device
+    raw_value: i8,
+    // the most recent value returned by the filter
+    filtered_value: i8,
+    // the most recent samples for the spike filter, oldest first
+    spike_samples: VecDeque<f64>,
+    // the most recent samples for the moving average, oldest first
+    average_samples: VecDeque<f64>,
+    exponential_value: Option<f64>,
+    // the latest raw value is sampled again at this instant
+    deadline: Option<Instant>,
+}
+
+impl BreathFilter {
+    pub fn new(config: FilterConfig) -> Self {
+        BreathFilter {
+            config,
+            raw_value: 0,
+            filtered_value: 0,
+            spike_samples: VecDeque::new(),
+            average_samples: VecDeque::new(),
+            exponential_value: None,
+            deadline: None,
+        }
+    }
+
+    pub fn set_config(&mut self, config: FilterConfig) {
+        if config != self.config {
+            self.config = config;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.raw_value = 0;
+        self.filtered_value = 0;
+        self.spike_samples.clear();
+        self.average_samples.clear();
+        self.exponential_value = None;
+        self.deadline = None;
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /**
+     * Returns the new filtered value, if it has changed.
+     */
+    pub fn handle_breath(&mut self, breath_value: i8, now: Instant) -> Option<i8> {
+        self.raw_value = breath_value;
+        self.sample(now)
+    }
+
+    /**
+     * Returns the new filtered value, if it has changed.
+     */
+    pub fn handle_timer(&mut self, now: Instant) -> Option<i8> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => self.sample(now),
+            _ => None,
+        }
+    }
+
+    fn sample(&mut self, now: Instant) -> Option<i8> {
+        let raw_value = f64::from(self.raw_value);
+        let mut value = raw_value;
+        let mut settled = true;
+
+        if self.config.spike_filter {
+            push_sample(&mut self.spike_samples, value, SPIKE_FILTER_SAMPLES);
+            value = median(&self.spike_samples);
+            settled &= self.spike_samples.iter().all(|sample| *sample == raw_value);
+        }
+
+        match self.config.smoothing {
+            Smoothing::Off => {},
+            Smoothing::MovingAverage => {
+                push_sample(&mut self.average_samples, value, self.config.smoothing_window());
+                value = self.average_samples.iter().sum::<f64>() / self.average_samples.len() as f64;
+                settled &= self.average_samples.iter().all(|sample| *sample == raw_value);
+            },
+            Smoothing::Exponential => {
+                // the same weight as a moving average over the same number of samples
+                let alpha = 2.0 / (self.config.smoothing_window() as f64 + 1.0);
+                let previous = self.exponential_value.unwrap_or(value);
+                let mut exponential = previous + alpha * (value - previous);
+
+                // stop once the difference no longer affects the rounded value
+                if (exponential - raw_value).abs() < 0.5 {
+                    exponential = raw_value;
+                }
+                else {
+                    settled = false;
+                }
+
+                self.exponential_value = Some(exponential);
+                value = exponential;
+            },
+        }
+
+        self.deadline = if settled { None } else { Some(now + Duration::from_millis(POLL_DELAY)) };
+
+        let filtered_value = self.shape(value);
+        if filtered_value == self.filtered_value {
+            return None;
+        }
+
+        self.filtered_value = filtered_value;
+        Some(filtered_value)
+    }
+
+    // Apply the deadzone, sensitivity and response curve to a smoothed value
+    fn shape(&self, value: f64) -> i8 {
+        let direction = if value < 0.0 { BreathDirection::Sip } else { BreathDirection::Puff };
+        let strength = value.abs();
+
+        if strength < f64::from(self.config.deadzone()) {
+            return 0;
+        }
+
+        let sensitivity = f64::from(self.config.sensitivity(direction)) / 100.0;
+        let strength = self.config.curve(direction).apply(strength * sensitivity / 100.0) * 100.0;
+
+        let strength = strength.round() as i8;
+        match direction {
+            BreathDirection::Puff => strength,
+            BreathDirection::Sip => -strength,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::AccelerationCurve;
+
+    // Sample the latest raw value at the poll rate until the filter settles, returning the changes
+    fn settle(filter: &mut BreathFilter, now: &mut Instant) -> Vec<i8> {
+        let mut values = Vec::new();
+        while let Some(deadline) = filter.next_deadline() {
+            *now = deadline;
+            values.extend(filter.handle_timer(*now));
+        }
+        values
+    }
+
+    #[test]
+    fn spike_filter_ignores_single_samples() {
+        let mut filter = BreathFilter::new(FilterConfig { spike_filter: true, ..FilterConfig::default() });
+        let now = Instant::now();
+
+        assert_eq!(filter.handle_breath(10, now), Some(10));
+        assert_eq!(filter.handle_breath(10, now), None);
+        assert_eq!(filter.handle_breath(10, now), None);
+        assert_eq!(filter.handle_breath(90, now), None);
+        assert_eq!(filter.handle_breath(10, now), None);
+
+        // a rise that lasts two samples is no spike
+        assert_eq!(filter.handle_breath(90, now), Some(90));
+    }
+
+    #[test]
+    fn moving_average_settles_on_the_raw_value() {
+        let mut filter = BreathFilter::new(FilterConfig { smoothing: Smoothing::MovingAverage, ..FilterConfig::default() });
+        let mut now = Instant::now();
+
+        assert_eq!(filter.handle_breath(0, now), None);
+        assert_eq!(filter.handle_breath(40, now), Some(20));
+        assert_eq!(filter.next_deadline(), Some(now + Duration::from_millis(POLL_DELAY)));
+        assert_eq!(settle(&mut filter, &mut now), vec![27, 30, 40]);
+        assert_eq!(filter.next_deadline(), None);
+    }
+
+    #[test]
+    fn exponential_smoothing_settles_on_the_raw_value() {
+        let mut filter = BreathFilter::new(FilterConfig { smoothing: Smoothing::Exponential, ..FilterConfig::default() });
+        let mut now = Instant::now();
+
+        assert_eq!(filter.handle_breath(0, now), None);
+        assert_eq!(filter.handle_breath(50, now), Some(20));
+        assert_eq!(settle(&mut filter, &mut now), vec![32, 39, 44, 46, 48, 49, 50]);
+        assert_eq!(filter.next_deadline(), None);
+    }
+
+    #[test]
+    fn deadzone_steps_from_zero_to_the_deadzone() {
+        let mut filter = BreathFilter::new(FilterConfig { deadzone: Some(10), ..FilterConfig::default() });
+        let now = Instant::now();
+
+        assert_eq!(filter.handle_breath(9, now), None);
+        assert_eq!(filter.handle_breath(10, now), Some(10));
+        assert_eq!(filter.handle_breath(-9, now), Some(0));
+        assert_eq!(filter.handle_breath(-10, now), Some(-10));
+        assert_eq!(filter.next_deadline(), None);
+    }
+
+    #[test]
+    fn shape_applies_sensitivity_and_curve_per_direction() {
+        let filter = BreathFilter::new(FilterConfig {
+            deadzone: Some(5),
+            puff_curve: AccelerationCurve::Quadratic,
+            puff_sensitivity: Some(200),
+            sip_sensitivity: Some(50),
+            ..FilterConfig::default()
+        });
+
+        assert_eq!(filter.shape(30.0), 36);
+        assert_eq!(filter.shape(60.0), 100);
+        assert_eq!(filter.shape(-60.0), -30);
+        assert_eq!(filter.shape(-100.0), -50);
+        // the deadzone applies to the breath before it is shaped
+        assert_eq!(filter.shape(4.0), 0);
+        assert_eq!(filter.shape(-5.0), -3);
+    }
+}
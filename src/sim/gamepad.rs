@@ -0,0 +1,143 @@
+use std::io;
+use indexmap::IndexSet;
+
+use crate::config::types::{GamepadConfig, GamepadLayout, strength_above_threshold};
+use crate::sim::types::{GAMEPAD_AXES, GamepadAxis, GamepadButton};
+
+#[cfg(target_os = "linux")]
+use crate::sim::uinput::UinputGamepad as GamepadDevice;
+
+#[cfg(not(target_os = "linux"))]
+struct GamepadDevice;
+
+#[cfg(not(target_os = "linux"))]
+impl GamepadDevice {
+    fn new() -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "virtual gamepads are only available on Linux"))
+    }
+
+    fn set_axis(&self, _axis: GamepadAxis, _value: i32) {}
+
+    fn set_button(&self, _button: GamepadButton, _pressed: bool) {}
+}
+
+pub type HeldGamepadButtons = IndexSet<GamepadButton>;
+
+/**
+ * The value of every gamepad axis for the given breath value.
+ */
+pub fn gamepad_axes(config: &GamepadConfig, breath_value: i8) -> Vec<(GamepadAxis, i32)> {
+    let strength = config.response_curve.apply(
+        strength_above_threshold(breath_value.saturating_abs(), config.deadzone())
+    );
+    let puff = breath_value > 0;
+
+    let trigger_value = |axis: GamepadAxis| (strength * f64::from(axis.range().1)).round() as i32;
+    // puffs move the stick in the positive direction, the Y axis points down
+    let stick_value = |axis: GamepadAxis, up: bool| {
+        let value = (strength * f64::from(axis.range().1)).round() as i32;
+        if puff != up { value } else { -value }
+    };
+
+    let active = match config.layout {
+        GamepadLayout::Triggers if puff => (GamepadAxis::RightTrigger, trigger_value(GamepadAxis::RightTrigger)),
+        GamepadLayout::Triggers => (GamepadAxis::LeftTrigger, trigger_value(GamepadAxis::LeftTrigger)),
+        GamepadLayout::LeftStickX => (GamepadAxis::LeftStickX, stick_value(GamepadAxis::LeftStickX, false)),
+        GamepadLayout::LeftStickY => (GamepadAxis::LeftStickY, stick_value(GamepadAxis::LeftStickY, true)),
+        GamepadLayout::RightStickX => (GamepadAxis::RightStickX, stick_value(GamepadAxis::RightStickX, false)),
+        GamepadLayout::RightStickY => (GamepadAxis::RightStickY, stick_value(GamepadAxis::RightStickY, true)),
+    };
+
+    // all other axes are at rest
+    GAMEPAD_AXES.iter()
+        .map(|axis| if *axis == active.0 { active } else { (*axis, 0) })
+        .collect()
+}
+
+/**
+ * Drives a virtual gamepad directly from the breath values: the breath strength is an analog
+ * trigger or stick, and hotkeys can hold gamepad buttons.
+ */
+pub struct GamepadOutput {
+    config: GamepadConfig,
+    device: Option<GamepadDevice>,
+    // the axis values and buttons as last sent to the device
+    axes: Vec<(GamepadAxis, i32)>,
+    buttons: HeldGamepadButtons,
+}
+
+impl GamepadOutput {
+    pub fn new() -> Self {
+        GamepadOutput {
+            config: GamepadConfig::default(),
+            device: None,
+            axes: Vec::new(),
+            buttons: IndexSet::new(),
+        }
+    }
+
+    /**
+     * Create or remove the virtual gamepad. Returns an error if the gamepad has just been enabled,
+     * but could not be created. The gamepad then remains disabled, so that it is created again
+     * once it is enabled in a later config.
+     */
+    pub fn set_config(&mut self, config: GamepadConfig) -> io::Result<()> {
+        let just_enabled = config.enabled && !self.config.enabled;
+        self.config = config;
+
+        if !self.config.enabled {
+            self.device = None;
+        }
+        else if just_enabled && self.device.is_none() {
+            match GamepadDevice::new() {
+                Ok(device) => self.device = Some(device),
+                Err(err) => {
+                    self.config.enabled = false;
+                    return Err(err);
+                },
+            }
+            self.axes.clear();
+            self.buttons.clear();
+        }
+
+        // the layout might have changed, make sure the previous axis is at rest
+        self.handle_breath(0);
+        Ok(())
+    }
+
+    pub fn handle_breath(&mut self, breath_value: i8) {
+        let Some(device) = &self.device else {
+            return;
+        };
+
+        let axes = gamepad_axes(&self.config, breath_value);
+        for &(axis, value) in &axes {
+            if !self.axes.contains(&(axis, value)) {
+                device.set_axis(axis, value);
+            }
+        }
+        self.axes = axes;
+    }
+
+    pub fn set_buttons(&mut self, buttons: HeldGamepadButtons) {
+        let Some(device) = &self.device else {
+            return;
+        };
+
+        for button in self.buttons.difference(&buttons) {
+            device.set_button(*button, false);
+        }
+
+        for button in buttons.difference(&self.buttons) {
+            device.set_button(*button, true);
+        }
+
+        self.buttons = buttons;
+    }
+}
+
+impl Default for GamepadOutput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,242 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::types::{BreathDirection, MAX_GESTURE_LENGTH};
+
+/**
+ * A breath that has been counted as part of a gesture.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureBreath {
+    pub direction: BreathDirection,
+    // the highest breath strength (percentage) of the breath
+    pub peak: i8,
+    pub duration: Duration,
+}
+
+/**
+ * Splits the stream of breath values into discrete breaths, and collects consecutive breaths into
+ * a sequence. A sequence is complete if no new breath starts within `window` after the previous
+ * breath has ended. A breath that lasts longer than `window` is not part of any sequence, it
+ * completes the sequence before it.
+ */
+pub struct GestureRecognizer {
+    threshold: i8,
+    window: Duration,
+    // the breath that is currently in progress, when it started and its peak strength
+    current_breath: Option<(BreathDirection, Instant, i8)>,
+    // true while the breath in progress is not counted, until it ends or changes direction
+    ignore_breath: Option<BreathDirection>,
+    sequence: Vec<GestureBreath>,
+    // the sequence is complete at this instant, unless a new breath starts before then
+    deadline: Option<Instant>,
+}
+
+impl GestureRecognizer {
+    pub fn new(threshold: i8, window: Duration) -> Self {
+        GestureRecognizer {
+            threshold,
+            window,
+            current_breath: None,
+            ignore_breath: None,
+            sequence: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.current_breath = None;
+        self.ignore_breath = None;
+        self.sequence.clear();
+        self.deadline = None;
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        // the breath in progress becomes too long for a gesture
+        let breath_deadline = self.current_breath.map(|(_, start, _)| start + self.window);
+
+        match (self.deadline, breath_deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /**
+     * The directions of the breaths in the sequence so far, including the breath in progress.
+     */
+    pub fn directions(&self) -> impl Iterator<Item = BreathDirection> + '_ {
+        self.sequence
+            .iter()
+            .map(|breath| breath.direction)
+            .chain(self.current_breath.map(|(direction, _, _)| direction))
+    }
+
+    fn end_breath(&mut self, now: Instant) {
+        if let Some((direction, start, peak)) = self.current_breath.take() {
+            if self.sequence.len() < MAX_GESTURE_LENGTH {
+                self.sequence.push(GestureBreath { direction, peak, duration: now - start });
+            }
+            self.deadline = Some(now + self.window);
+        }
+    }
+
+    pub fn handle_breath(&mut self, breath_value: i8, now: Instant) {
+        let direction = if breath_value < 0 { BreathDirection::Sip } else { BreathDirection::Puff };
+
+        if breath_value.abs() < self.threshold {
+            self.ignore_breath = None;
+            self.end_breath(now);
+            return;
+        }
+
+        if self.ignore_breath == Some(direction) {
+            return;
+        }
+        self.ignore_breath = None;
+
+        match &mut self.current_breath {
+            Some((current_direction, _, peak)) if *current_direction == direction => {
+                *peak = (*peak).max(breath_value.abs());
+            },
+            _ => {
+                // the direction might flip without passing through neutral
+                self.end_breath(now);
+                self.current_breath = Some((direction, now, breath_value.abs()));
+                self.deadline = None;
+            },
+        }
+    }
+
+    /**
+     * Don't count the breath in progress. The breaths before it remain in the sequence.
+     */
+    pub fn ignore_current_breath(&mut self) {
+        if let Some((direction, _, _)) = self.current_breath.take() {
+            self.ignore_breath = Some(direction);
+        }
+    }
+
+    /**
+     * Remove the breaths that have ended from the sequence, and return them.
+     */
+    pub fn take_sequence(&mut self) -> Vec<GestureBreath> {
+        self.deadline = None;
+        std::mem::take(&mut self.sequence)
+    }
+
+    // Returns the completed sequence of breaths, if the deadline has passed or the breath in
+    // progress has become too long to be part of a gesture
+    pub fn handle_timer(&mut self, now: Instant) -> Option<Vec<GestureBreath>> {
+        if self.current_breath.is_some_and(|(_, start, _)| now >= start + self.window) {
+            self.ignore_current_breath();
+            let sequence = self.take_sequence();
+            return if sequence.is_empty() { None } else { Some(sequence) };
+        }
+
+        match self.deadline {
+            Some(deadline) if deadline <= now => Some(self.take_sequence()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(600);
+
+    fn recognizer() -> GestureRecognizer {
+        GestureRecognizer::new(20, WINDOW)
+    }
+
+    fn ms(milliseconds: u64) -> Duration {
+        Duration::from_millis(milliseconds)
+    }
+
+    // Breathe with the given strength from `start` for `duration`, returning the end of the breath
+    fn breathe(recognizer: &mut GestureRecognizer, breath_value: i8, start: Instant, duration: Duration) -> Instant {
+        recognizer.handle_breath(breath_value, start);
+        recognizer.handle_breath(0, start + duration);
+        start + duration
+    }
+
+    fn directions(sequence: &[GestureBreath]) -> Vec<BreathDirection> {
+        sequence.iter().map(|breath| breath.direction).collect()
+    }
+
+    #[test]
+    fn sequence_completes_after_the_window() {
+        let mut recognizer = recognizer();
+        let start = Instant::now();
+
+        // too weak to count
+        breathe(&mut recognizer, 10, start, ms(100));
+        assert_eq!(recognizer.next_deadline(), None);
+
+        recognizer.handle_breath(30, start + ms(200));
+        recognizer.handle_breath(60, start + ms(250));
+        let end = breathe(&mut recognizer, 40, start + ms(280), ms(20));
+        assert_eq!(recognizer.next_deadline(), Some(end + WINDOW));
+
+        // a breath within the window continues the sequence
+        let end = breathe(&mut recognizer, -50, end + WINDOW - ms(1), ms(100));
+        assert_eq!(recognizer.directions().collect::<Vec<_>>(), vec![BreathDirection::Puff, BreathDirection::Sip]);
+
+        assert_eq!(recognizer.handle_timer(end + WINDOW - ms(1)), None);
+        assert_eq!(recognizer.handle_timer(end + WINDOW), Some(vec![
+            GestureBreath { direction: BreathDirection::Puff, peak: 60, duration: ms(100) },
+            GestureBreath { direction: BreathDirection::Sip, peak: 50, duration: ms(100) },
+        ]));
+        assert_eq!(recognizer.next_deadline(), None);
+        assert_eq!(recognizer.directions().count(), 0);
+    }
+
+    #[test]
+    fn direction_change_without_neutral_starts_a_new_breath() {
+        let mut recognizer = recognizer();
+        let start = Instant::now();
+
+        recognizer.handle_breath(50, start);
+        let end = breathe(&mut recognizer, -50, start + ms(100), ms(100));
+
+        let sequence = recognizer.handle_timer(end + WINDOW).unwrap();
+        assert_eq!(directions(&sequence), vec![BreathDirection::Puff, BreathDirection::Sip]);
+    }
+
+    #[test]
+    fn long_breath_completes_the_sequence_before_it() {
+        let mut recognizer = recognizer();
+        let start = Instant::now();
+
+        let end = breathe(&mut recognizer, -50, start, ms(100));
+        recognizer.handle_breath(50, end + ms(100));
+        assert_eq!(recognizer.next_deadline(), Some(end + ms(100) + WINDOW));
+
+        let sequence = recognizer.handle_timer(end + ms(100) + WINDOW).unwrap();
+        assert_eq!(directions(&sequence), vec![BreathDirection::Sip]);
+
+        // the rest of the long breath is not counted
+        recognizer.handle_breath(60, end + ms(800));
+        assert_eq!(recognizer.directions().count(), 0);
+        recognizer.handle_breath(0, end + ms(900));
+        assert_eq!(recognizer.next_deadline(), None);
+
+        // a long breath on its own is no sequence
+        recognizer.handle_breath(50, end + ms(1000));
+        assert_eq!(recognizer.handle_timer(end + ms(1000) + WINDOW), None);
+    }
+
+    #[test]
+    fn sequence_is_limited_to_the_max_gesture_length() {
+        let mut recognizer = recognizer();
+        let mut now = Instant::now();
+
+        for _ in 0..MAX_GESTURE_LENGTH + 1 {
+            now = breathe(&mut recognizer, 50, now, ms(100)) + ms(100);
+        }
+
+        let sequence = recognizer.handle_timer(now + WINDOW).unwrap();
+        assert_eq!(sequence.len(), MAX_GESTURE_LENGTH);
+    }
+}
@@ -1,58 +1,247 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
 use indexmap::IndexSet;
 use tokio::spawn;
 use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Duration, Instant};
 use futures::StreamExt;
 use futures::channel::mpsc::{channel, Sender};
-use log::warn;
+use log::{info, warn};
 use tokio_util::sync::CancellationToken;
-use rdev::{EventType, simulate};
-use crate::sim::types::{HeldButtons, InputSimCommand};
+use crate::sim::backend::InputBackend;
+use crate::sim::safety::set_pressed_buttons;
+use crate::config::types::MacroStep;
+use crate::sim::types::{Button, HeldButtons, InputSimCommand, RepeatTap};
 
-fn send(event_type: &EventType) {
-    if let Err(err) = simulate(event_type) {
-        warn!("Failed to simulate {:?}: {:?}", event_type, err);
+/**
+ * How often (milliseconds) the mouse pointer is moved while it has a velocity.
+ */
+const POINTER_TICK_INTERVAL: u64 = 16;
+
+/**
+ * How long (milliseconds) each key is pressed while running a macro.
+ */
+const MACRO_KEY_DURATION: u64 = 20;
+
+/**
+ * How long (milliseconds) to wait between the keys of a macro.
+ */
+const MACRO_KEY_GAP: u64 = 20;
+
+enum MacroAction {
+    SetButtons(HeldButtons),
+    Wait(Duration),
+}
+
+fn push_macro_tap(actions: &mut VecDeque<MacroAction>, buttons: HeldButtons) {
+    actions.push_back(MacroAction::SetButtons(buttons));
+    actions.push_back(MacroAction::Wait(Duration::from_millis(MACRO_KEY_DURATION)));
+    actions.push_back(MacroAction::SetButtons(IndexSet::new()));
+    actions.push_back(MacroAction::Wait(Duration::from_millis(MACRO_KEY_GAP)));
+}
+
+fn expand_macro(steps: Vec<MacroStep>) -> VecDeque<MacroAction> {
+    let mut actions = VecDeque::new();
+
+    for step in steps {
+        match step {
+            MacroStep::Chord(buttons) => {
+                push_macro_tap(&mut actions, buttons.into_iter().collect());
+            },
+            MacroStep::Text(text) => {
+                for character in text.chars() {
+                    match Button::for_char(character) {
+                        Some(buttons) => push_macro_tap(&mut actions, buttons),
+                        None => warn!("Unable to type character {:?}", character),
+                    }
+                }
+            },
+            MacroStep::Delay(delay) => {
+                actions.push_back(MacroAction::Wait(Duration::from_millis(u64::from(delay))));
+            },
+        }
+    }
+
+    actions
+}
+
+// Run the macro actions up until the next wait. Returns when the next action should be run, or None
+// if the macro has finished
+fn advance_macro(actions: &mut VecDeque<MacroAction>, macro_buttons: &mut HeldButtons, now: Instant) -> Option<Instant> {
+    while let Some(action) = actions.pop_front() {
+        match action {
+            MacroAction::SetButtons(buttons) => *macro_buttons = buttons,
+            MacroAction::Wait(duration) => return Some(now + duration),
+        }
+    }
+
+    None
+}
+
+// Press and release buttons so that exactly the given buttons are pressed at the OS level. Buttons
+// are pressed in order and released in reverse order, so that the modifiers of a chord surround its key
+fn sync_pressed_buttons(backend: &dyn InputBackend, pressed_buttons: &mut HeldButtons, new_buttons: HeldButtons) {
+    for button in pressed_buttons.difference(&new_buttons).rev() {
+        backend.release(button);
+    }
+
+    for button in new_buttons.difference(pressed_buttons) {
+        backend.press(button);
+    }
+
+    *pressed_buttons = new_buttons;
+    set_pressed_buttons(pressed_buttons);
+}
+
+// Press the buttons of a tap, after finishing the previous tap. Returns when the buttons should be released
+fn start_tap(
+    backend: &dyn InputBackend,
+    pressed_buttons: &mut HeldButtons,
+    held_buttons: &HeldButtons,
+    tapped_buttons: &mut HeldButtons,
+    buttons: HeldButtons,
+    duration: Duration,
+) -> Instant {
+    if !tapped_buttons.is_empty() {
+        // finish the previous tap first, otherwise tapping the same button twice would only result
+        // in one click
+        tapped_buttons.clear();
+        sync_pressed_buttons(backend, pressed_buttons, held_buttons.clone());
     }
+
+    *tapped_buttons = buttons;
+    Instant::now() + duration
 }
 
-pub fn input_sim_task(cancel: CancellationToken) -> (Sender<InputSimCommand>, JoinHandle<()>) {
+pub fn input_sim_task(cancel: CancellationToken, backend: Arc<dyn InputBackend>) -> (Sender<InputSimCommand>, JoinHandle<()>) {
     let (tx, mut rx) = channel::<InputSimCommand>(128);
 
     let handle = spawn(async move {
+        // buttons that are actually pressed at the OS level
+        let mut pressed_buttons: HeldButtons = IndexSet::new();
+        // buttons that breath_input_sim wants to be held
         let mut held_buttons: HeldButtons = IndexSet::new();
+        // buttons that are pressed because of a tap, until tap_deadline
+        let mut tapped_buttons: HeldButtons = IndexSet::new();
+        let mut tap_deadline: Option<Instant> = None;
+        // buttons that are tapped repeatedly, the next tap starts at repeat_deadline
+        let mut repeat: Option<RepeatTap> = None;
+        let mut repeat_deadline: Option<Instant> = None;
+        let mut repeat_last_tap: Option<Instant> = None;
+        // the remaining actions of the running macro, the next action runs at macro_deadline
+        let mut macro_actions: VecDeque<MacroAction> = VecDeque::new();
+        let mut macro_buttons: HeldButtons = IndexSet::new();
+        let mut macro_deadline: Option<Instant> = None;
+        // pixels per tick, the fraction of a pixel that has not been moved yet is kept in pointer_remainder
+        let mut pointer_velocity: (f64, f64) = (0.0, 0.0);
+        let mut pointer_remainder: (f64, f64) = (0.0, 0.0);
+        let mut pointer_deadline: Option<Instant> = None;
 
         'mainloop: loop {
             tokio::select! {
                 _ = cancel.cancelled() => {
                     break 'mainloop;
                 },
+                _ = sleep_until(tap_deadline.unwrap_or_else(Instant::now)), if tap_deadline.is_some() => {
+                    tapped_buttons.clear();
+                    tap_deadline = None;
+                },
+                _ = sleep_until(repeat_deadline.unwrap_or_else(Instant::now)), if repeat_deadline.is_some() => {
+                    if let Some(repeat) = &repeat {
+                        let now = Instant::now();
+                        // release the buttons halfway through the interval at the latest, so that every
+                        // tap is a separate press
+                        let duration = repeat.tap_duration.min(repeat.interval / 2);
+                        tap_deadline = Some(start_tap(backend.as_ref(), &mut pressed_buttons, &held_buttons, &mut tapped_buttons, repeat.buttons.clone(), duration));
+                        repeat_last_tap = Some(now);
+                        repeat_deadline = Some(now + repeat.interval);
+                    }
+                },
+                _ = sleep_until(macro_deadline.unwrap_or_else(Instant::now)), if macro_deadline.is_some() => {
+                    macro_deadline = advance_macro(&mut macro_actions, &mut macro_buttons, Instant::now());
+                },
+                _ = sleep_until(pointer_deadline.unwrap_or_else(Instant::now)), if pointer_deadline.is_some() => {
+                    let x = pointer_remainder.0 + pointer_velocity.0;
+                    let y = pointer_remainder.1 + pointer_velocity.1;
+                    pointer_remainder = (x.fract(), y.fract());
+
+                    if x.trunc() != 0.0 || y.trunc() != 0.0 {
+                        backend.move_pointer(x.trunc(), y.trunc());
+                    }
+
+                    pointer_deadline = pointer_deadline.map(|deadline| deadline + Duration::from_millis(POINTER_TICK_INTERVAL));
+                },
                 Some(command) = rx.next() => {
                     match command {
                         InputSimCommand::SetHeldButtons(new_buttons) => {
-                            for button in held_buttons.difference(&new_buttons) {
-                                if let Some(btn) = button.rdev_mouse_button() {
-                                    send(&EventType::ButtonRelease(btn));
-                                }
-                                else if let Some(key) = button.rdev_key() {
-                                    send(&EventType::KeyRelease(key));
-                                }
+                            held_buttons = new_buttons;
+                        },
+                        InputSimCommand::Tap(buttons, duration) => {
+                            tap_deadline = Some(start_tap(backend.as_ref(), &mut pressed_buttons, &held_buttons, &mut tapped_buttons, buttons, duration));
+                        },
+                        InputSimCommand::SetRepeat(new_repeat) => {
+                            match (&repeat, new_repeat) {
+                                (Some(current), Some(new_repeat)) if current.buttons == new_repeat.buttons => {
+                                    // only the rate has changed, the next tap follows the new interval
+                                    if let Some(last_tap) = repeat_last_tap {
+                                        repeat_deadline = Some(last_tap + new_repeat.interval);
+                                    }
+                                    repeat = Some(new_repeat);
+                                },
+                                (_, Some(new_repeat)) => {
+                                    repeat = Some(new_repeat);
+                                    repeat_deadline = Some(Instant::now());
+                                },
+                                (_, None) => {
+                                    repeat = None;
+                                    repeat_deadline = None;
+                                    repeat_last_tap = None;
+                                },
                             }
-
-                            for button in new_buttons.difference(&held_buttons) {
-                                if let Some(btn) = button.rdev_mouse_button() {
-                                    send(&EventType::ButtonPress(btn));
-                                }
-                                else if let Some(key) = button.rdev_key() {
-                                    send(&EventType::KeyPress(key));
-                                }
+                        },
+                        InputSimCommand::RunMacro(steps) => {
+                            macro_actions = expand_macro(steps);
+                            macro_buttons.clear();
+                            macro_deadline = advance_macro(&mut macro_actions, &mut macro_buttons, Instant::now());
+                        },
+                        InputSimCommand::CancelMacro => {
+                            if macro_deadline.is_some() {
+                                info!("Cancelling macro");
                             }
+                            macro_actions.clear();
+                            macro_buttons.clear();
+                            macro_deadline = None;
+                        },
+                        InputSimCommand::Scroll(notches_x, notches_y) => {
+                            backend.scroll(notches_x, notches_y);
+                        },
+                        InputSimCommand::SetPointerVelocity(velocity) => {
+                            pointer_velocity = velocity;
 
-                            held_buttons = new_buttons;
+                            if velocity == (0.0, 0.0) {
+                                pointer_remainder = (0.0, 0.0);
+                                pointer_deadline = None;
+                            }
+                            else if pointer_deadline.is_none() {
+                                pointer_deadline = Some(Instant::now());
+                            }
                         },
                     }
                 },
             }
+
+            let mut desired_buttons: HeldButtons = held_buttons.union(&tapped_buttons).copied().collect();
+            desired_buttons.extend(macro_buttons.iter());
+            if desired_buttons != pressed_buttons {
+                sync_pressed_buttons(backend.as_ref(), &mut pressed_buttons, desired_buttons);
+            }
+        }
+
+        if !pressed_buttons.is_empty() {
+            info!("Releasing {:?} before exiting", pressed_buttons);
+            sync_pressed_buttons(backend.as_ref(), &mut pressed_buttons, IndexSet::new());
         }
     });
 
     return (tx, handle);
-}
\ No newline at end of file
+}
@@ -1,3 +1,14 @@
+pub mod artifact;
+pub mod backend;
 pub mod breath_input_sim;
+pub mod cursor;
+pub mod filter;
+pub mod gamepad;
+pub mod gesture;
 pub mod input_sim;
+pub mod morse;
+pub mod pointer;
+pub mod safety;
 pub mod types;
+#[cfg(target_os = "linux")]
+pub mod uinput;
@@ -0,0 +1,249 @@
+use std::time::Duration;
+use log::info;
+use tokio::time::Instant;
+
+use crate::sim::types::Button;
+
+/**
+ * The maximum number of dots and dashes in one character.
+ */
+const MAX_CODE_LENGTH: usize = 6;
+
+// Letters and digits follow International Morse code, the editing keys follow the conventions of
+// common assistive Morse systems.
+const MORSE_TABLE: [(&str, Button); 39] = [
+    (".-", Button::KeyA),
+    ("-...", Button::KeyB),
+    ("-.-.", Button::KeyC),
+    ("-..", Button::KeyD),
+    (".", Button::KeyE),
+    ("..-.", Button::KeyF),
+    ("--.", Button::KeyG),
+    ("....", Button::KeyH),
+    ("..", Button::KeyI),
+    (".---", Button::KeyJ),
+    ("-.-", Button::KeyK),
+    (".-..", Button::KeyL),
+    ("--", Button::KeyM),
+    ("-.", Button::KeyN),
+    ("---", Button::KeyO),
+    (".--.", Button::KeyP),
+    ("--.-", Button::KeyQ),
+    (".-.", Button::KeyR),
+    ("...", Button::KeyS),
+    ("-", Button::KeyT),
+    ("..-", Button::KeyU),
+    ("...-", Button::KeyV),
+    (".--", Button::KeyW),
+    ("-..-", Button::KeyX),
+    ("-.--", Button::KeyY),
+    ("--..", Button::KeyZ),
+    ("-----", Button::Num0),
+    (".----", Button::Num1),
+    ("..---", Button::Num2),
+    ("...--", Button::Num3),
+    ("....-", Button::Num4),
+    (".....", Button::Num5),
+    ("-....", Button::Num6),
+    ("--...", Button::Num7),
+    ("---..", Button::Num8),
+    ("----.", Button::Num9),
+    ("..--", Button::Space),
+    (".-.-", Button::Return),
+    ("----", Button::Backspace),
+];
+
+pub fn decode_morse(code: &str) -> Option<Button> {
+    MORSE_TABLE.iter()
+        .find(|(entry, _)| *entry == code)
+        .map(|(_, button)| *button)
+}
+
+/**
+ * Decodes puffs into characters: a short puff is a dot, a long puff is a dash. A character ends
+ * when the user sips, or pauses for longer than `character_gap`.
+ */
+pub struct MorseDecoder {
+    threshold: i8,
+    dash_duration: Duration,
+    character_gap: Duration,
+    // the start of the puff that is currently in progress
+    puff_start: Option<Instant>,
+    // true while sipping, so that one sip ends only one character
+    sipping: bool,
+    // the dots and dashes of the current character
+    code: String,
+    // the current character is complete at this instant, unless a new puff starts before then
+    deadline: Option<Instant>,
+}
+
+impl MorseDecoder {
+    pub fn new(threshold: i8, dash_duration: Duration, character_gap: Duration) -> Self {
+        MorseDecoder {
+            threshold,
+            dash_duration,
+            character_gap,
+            puff_start: None,
+            sipping: false,
+            code: String::new(),
+            deadline: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.puff_start = None;
+        self.sipping = false;
+        self.code.clear();
+        self.deadline = None;
+    }
+
+    // The dots and dashes entered so far for the current character
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn end_character(&mut self) -> Option<Button> {
+        self.deadline = None;
+
+        if self.code.is_empty() {
+            return None;
+        }
+
+        let code = std::mem::take(&mut self.code);
+        let button = decode_morse(&code);
+        if button.is_none() {
+            info!("Unknown morse code {}", code);
+        }
+        button
+    }
+
+    // Returns the button to type, if a character has been completed
+    pub fn handle_breath(&mut self, breath_value: i8, now: Instant) -> Option<Button> {
+        if breath_value >= self.threshold {
+            self.sipping = false;
+
+            if self.puff_start.is_none() {
+                self.puff_start = Some(now);
+                self.deadline = None;
+            }
+            return None;
+        }
+
+        if let Some(start) = self.puff_start.take() {
+            let symbol = if now - start >= self.dash_duration { '-' } else { '.' };
+            if self.code.len() < MAX_CODE_LENGTH {
+                self.code.push(symbol);
+            }
+            self.deadline = Some(now + self.character_gap);
+        }
+
+        if breath_value <= -self.threshold {
+            if !self.sipping {
+                self.sipping = true;
+                return self.end_character();
+            }
+        }
+        else {
+            self.sipping = false;
+        }
+
+        None
+    }
+
+    // Returns the button to type, if the pause after the last puff has been long enough
+    pub fn handle_timer(&mut self, now: Instant) -> Option<Button> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => self.end_character(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    const DASH_DURATION: Duration = Duration::from_millis(300);
+    const CHARACTER_GAP: Duration = Duration::from_millis(1000);
+
+    fn decoder() -> MorseDecoder {
+        MorseDecoder::new(20, DASH_DURATION, CHARACTER_GAP)
+    }
+
+    // Puff for the given duration starting at `start`, returning the end of the puff
+    fn puff(decoder: &mut MorseDecoder, start: Instant, duration: Duration) -> Instant {
+        assert_eq!(decoder.handle_breath(50, start), None);
+        assert_eq!(decoder.handle_breath(0, start + duration), None);
+        start + duration
+    }
+
+    #[test]
+    fn morse_table_codes_are_unique() {
+        let codes: HashSet<&str> = MORSE_TABLE.iter().map(|(code, _)| *code).collect();
+        assert_eq!(codes.len(), MORSE_TABLE.len());
+        assert!(codes.iter().all(|code| code.len() <= MAX_CODE_LENGTH));
+
+        assert_eq!(decode_morse("..."), Some(Button::KeyS));
+        assert_eq!(decode_morse("----"), Some(Button::Backspace));
+        assert_eq!(decode_morse(""), None);
+    }
+
+    #[test]
+    fn dash_duration_separates_dots_and_dashes() {
+        let mut decoder = decoder();
+        let now = Instant::now();
+
+        let now = puff(&mut decoder, now, DASH_DURATION - Duration::from_millis(1));
+        assert_eq!(decoder.code(), ".");
+        puff(&mut decoder, now + Duration::from_millis(100), DASH_DURATION);
+        assert_eq!(decoder.code(), ".-");
+    }
+
+    #[test]
+    fn character_ends_after_the_gap() {
+        let mut decoder = decoder();
+
+        let end = puff(&mut decoder, Instant::now(), DASH_DURATION);
+        assert_eq!(decoder.next_deadline(), Some(end + CHARACTER_GAP));
+        assert_eq!(decoder.handle_timer(end + CHARACTER_GAP - Duration::from_millis(1)), None);
+
+        // a puff within the gap continues the character
+        let end = puff(&mut decoder, end + CHARACTER_GAP - Duration::from_millis(1), Duration::from_millis(100));
+        assert_eq!(decoder.code(), "-.");
+        assert_eq!(decoder.handle_timer(end + CHARACTER_GAP), Some(Button::KeyN));
+        assert_eq!(decoder.code(), "");
+        assert_eq!(decoder.next_deadline(), None);
+    }
+
+    #[test]
+    fn sip_ends_the_character() {
+        let mut decoder = decoder();
+
+        let end = puff(&mut decoder, Instant::now(), Duration::from_millis(100));
+        assert_eq!(decoder.handle_breath(-50, end), Some(Button::KeyE));
+
+        let end = puff(&mut decoder, end + Duration::from_millis(100), Duration::from_millis(100));
+        assert_eq!(decoder.handle_breath(-50, end), Some(Button::KeyE));
+        // holding the sip does not end another character
+        assert_eq!(decoder.handle_breath(-60, end), None);
+    }
+
+    #[test]
+    fn unknown_code_is_dropped() {
+        let mut decoder = decoder();
+        let mut now = Instant::now();
+
+        for _ in 0..MAX_CODE_LENGTH + 1 {
+            now = puff(&mut decoder, now, Duration::from_millis(100)) + Duration::from_millis(100);
+        }
+        assert_eq!(decoder.code(), "......");
+        assert_eq!(decoder.handle_timer(now + CHARACTER_GAP), None);
+        assert_eq!(decoder.code(), "");
+        assert_eq!(decoder.next_deadline(), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::types::{AccelerationCurve, strength_above_threshold};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerDirection {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl PointerDirection {
+    // The next direction, clockwise
+    pub fn rotate(&self) -> PointerDirection {
+        match self {
+            PointerDirection::Up => PointerDirection::UpRight,
+            PointerDirection::UpRight => PointerDirection::Right,
+            PointerDirection::Right => PointerDirection::DownRight,
+            PointerDirection::DownRight => PointerDirection::Down,
+            PointerDirection::Down => PointerDirection::DownLeft,
+            PointerDirection::DownLeft => PointerDirection::Left,
+            PointerDirection::Left => PointerDirection::UpLeft,
+            PointerDirection::UpLeft => PointerDirection::Up,
+        }
+    }
+
+    // Unit vector in screen coordinates (y increases downwards)
+    pub fn vector(&self) -> (f64, f64) {
+        let diagonal = std::f64::consts::FRAC_1_SQRT_2;
+        match self {
+            PointerDirection::Up => (0.0, -1.0),
+            PointerDirection::UpRight => (diagonal, -diagonal),
+            PointerDirection::Right => (1.0, 0.0),
+            PointerDirection::DownRight => (diagonal, diagonal),
+            PointerDirection::Down => (0.0, 1.0),
+            PointerDirection::DownLeft => (-diagonal, diagonal),
+            PointerDirection::Left => (-1.0, 0.0),
+            PointerDirection::UpLeft => (-diagonal, -diagonal),
+        }
+    }
+}
+
+impl std::fmt::Display for PointerDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            PointerDirection::Up => "Up",
+            PointerDirection::UpRight => "Up right",
+            PointerDirection::Right => "Right",
+            PointerDirection::DownRight => "Down right",
+            PointerDirection::Down => "Down",
+            PointerDirection::DownLeft => "Down left",
+            PointerDirection::Left => "Left",
+            PointerDirection::UpLeft => "Up left",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+/**
+ * Turns breath into mouse pointer movement: sipping rotates the direction indicator clockwise, and
+ * puffing moves the pointer in the selected direction. The speed follows the breath strength.
+ */
+pub struct PointerController {
+    threshold: i8,
+    // pixels per tick at full breath strength
+    max_speed: f64,
+    acceleration: AccelerationCurve,
+    rotation_interval: Duration,
+    direction: PointerDirection,
+    // while sipping, the direction rotates again at this instant
+    rotation_deadline: Option<Instant>,
+    // pixels per tick
+    velocity: (f64, f64),
+}
+
+impl PointerController {
+    pub fn new(threshold: i8, max_speed: u32, acceleration: AccelerationCurve, rotation_interval: Duration) -> Self {
+        PointerController {
+            threshold,
+            max_speed: f64::from(max_speed),
+            acceleration,
+            rotation_interval,
+            direction: PointerDirection::Up,
+            rotation_deadline: None,
+            velocity: (0.0, 0.0),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.rotation_deadline = None;
+        self.velocity = (0.0, 0.0);
+    }
+
+    pub fn direction(&self) -> PointerDirection {
+        self.direction
+    }
+
+    pub fn velocity(&self) -> (f64, f64) {
+        self.velocity
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.rotation_deadline
+    }
+
+    pub fn handle_breath(&mut self, breath_value: i8, now: Instant) {
+        if breath_value <= -self.threshold {
+            self.velocity = (0.0, 0.0);
+
+            // rotate once as soon as the sip starts, and keep rotating for as long as it lasts
+            if self.rotation_deadline.is_none() {
+                self.direction = self.direction.rotate();
+                self.rotation_deadline = Some(now + self.rotation_interval);
+            }
+            return;
+        }
+
+        self.rotation_deadline = None;
+
+        if breath_value >= self.threshold {
+            // start moving slowly at the threshold, reach the maximum speed at full strength
+            let strength = strength_above_threshold(breath_value, self.threshold);
+            let speed = self.max_speed * self.acceleration.apply(strength);
+            let (x, y) = self.direction.vector();
+            self.velocity = (x * speed, y * speed);
+        }
+        else {
+            self.velocity = (0.0, 0.0);
+        }
+    }
+
+    pub fn handle_timer(&mut self, now: Instant) {
+        if let Some(deadline) = self.rotation_deadline {
+            if deadline <= now {
+                self.direction = self.direction.rotate();
+                self.rotation_deadline = Some(deadline + self.rotation_interval);
+            }
+        }
+    }
+}
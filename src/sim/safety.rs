@@ -0,0 +1,57 @@
+use std::panic;
+use std::sync::{Arc, Mutex, OnceLock};
+use log::warn;
+
+use crate::sim::backend::InputBackend;
+use crate::sim::types::{Button, HeldButtons};
+
+/**
+ * The buttons that are currently pressed at the OS level, according to input_sim.
+ * This is kept outside of input_sim_task so that the panic hook can release them.
+ */
+static PRESSED_BUTTONS: Mutex<Vec<Button>> = Mutex::new(Vec::new());
+
+/**
+ * The backend that pressed the buttons in PRESSED_BUTTONS.
+ */
+static BACKEND: OnceLock<Arc<dyn InputBackend>> = OnceLock::new();
+
+pub fn set_pressed_buttons(buttons: &HeldButtons) {
+    let mut pressed = PRESSED_BUTTONS.lock().unwrap_or_else(|err| err.into_inner());
+    pressed.clear();
+    pressed.extend(buttons.iter());
+}
+
+/**
+ * Release every button that input_sim has pressed, without involving input_sim_task.
+ */
+pub fn release_pressed_buttons() {
+    // the mutex might have been poisoned by the panic that we are handling
+    let mut pressed = PRESSED_BUTTONS.lock().unwrap_or_else(|err| err.into_inner());
+
+    let Some(backend) = BACKEND.get() else {
+        return;
+    };
+
+    for button in pressed.drain(..).rev() {
+        warn!("Releasing {}", button);
+        backend.release(&button);
+    }
+}
+
+/**
+ * Make sure that no mouse button or key remains pressed if any thread panics.
+ */
+pub fn install_panic_hook(backend: Arc<dyn InputBackend>) {
+    if BACKEND.set(backend).is_err() {
+        warn!("The panic hook has already been installed");
+        return;
+    }
+
+    let previous_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        release_pressed_buttons();
+        previous_hook(info);
+    }));
+}
@@ -1,8 +1,10 @@
+use std::time::Duration;
 use indexmap::IndexSet;
 use serde::{Deserialize, Serialize};
 use rdev;
 
-use crate::config::types::Config;
+use crate::config::types::{Config, HotkeyConfig, MacroStep};
+use crate::sim::pointer::PointerDirection;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 // Sorted in a way that sorta makes sense for display in a dropdown
@@ -10,6 +12,9 @@ pub enum Button {
     MouseLeft,
     MouseRight,
     MouseMiddle,
+    // also known as mouse button 4 and 5
+    MouseBack,
+    MouseForward,
 
     UpArrow,
     RightArrow,
@@ -115,12 +120,47 @@ pub enum Button {
     KpMultiply,
     KpPlus,
     KpReturn,
+
+    VolumeUp,
+    VolumeDown,
+    VolumeMute,
+    MediaPlayPause,
+    MediaNext,
+    MediaPrevious,
+    MediaStop,
+
+    // a key that is not listed above, by its platform key code: the X11 keycode on Linux, the
+    // virtual-key code on Windows, or the virtual key code on macOS
+    KeyCode(u32),
+    // a mouse button that is not listed above, by its platform button number: the X11 button on
+    // Linux (8 and 9 are back and forward), or the X button on Windows (1 and 2)
+    MouseButton(u8),
+    // a character, typed regardless of the keyboard layout. It is typed once when pressed
+    Char(char),
+}
+
+/**
+ * The highest evdev key code (KEY_MAX).
+ */
+pub const EVDEV_KEY_MAX: u16 = 0x2ff;
+
+/**
+ * Whether an evdev key code is a keyboard key, rather than a mouse, joystick or tablet button.
+ */
+pub fn is_evdev_keyboard_key(code: u16) -> bool {
+    // leaves out BTN_MISC to BTN_GEAR_UP, BTN_DPAD_* and BTN_TRIGGER_HAPPY*
+    matches!(code, 1..=0xff | 0x160..=0x21f | 0x224..=0x2bf | 0x2e8..=EVDEV_KEY_MAX)
 }
 
-pub const BUTTONS: [Button; 107] = [
+/**
+ * The buttons that can be chosen from a list. KeyCode, MouseButton and Char are not included.
+ */
+pub const BUTTONS: [Button; 116] = [
     Button::MouseLeft,
     Button::MouseRight,
     Button::MouseMiddle,
+    Button::MouseBack,
+    Button::MouseForward,
     Button::UpArrow,
     Button::RightArrow,
     Button::DownArrow,
@@ -225,21 +265,224 @@ pub const BUTTONS: [Button; 107] = [
     Button::KpMultiply,
     Button::KpPlus,
     Button::KpReturn,
+    Button::VolumeUp,
+    Button::VolumeDown,
+    Button::VolumeMute,
+    Button::MediaPlayPause,
+    Button::MediaNext,
+    Button::MediaPrevious,
+    Button::MediaStop,
 ];
 
 impl Button {
+    /**
+     * Find a button by the name of its enum variant (case insensitive), for example "ControlLeft".
+     * Buttons that are not listed can be written as `KeyCode(123)`, `MouseButton(8)` or `Char(é)`.
+     */
+    pub fn from_name(name: &str) -> Option<Button> {
+        let named = BUTTONS.iter()
+            .find(|button| format!("{:?}", button).eq_ignore_ascii_case(name))
+            .copied();
+        if named.is_some() {
+            return named;
+        }
+
+        let (kind, argument) = name.strip_suffix(')')?.split_once('(')?;
+        let argument = argument.trim();
+
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "keycode" => argument.parse().ok().map(Button::KeyCode),
+            "mousebutton" => argument.parse().ok().map(Button::MouseButton),
+            "char" => {
+                // the Debug notation quotes the character
+                let argument = argument.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')).unwrap_or(argument);
+                let mut chars = argument.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(character), None) => Some(Button::Char(character)),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /**
+     * The buttons to press to type the given character, assuming a US keyboard layout.
+     */
+    pub fn for_char(character: char) -> Option<HeldButtons> {
+        let (button, shift) = match character {
+            'a' => (Button::KeyA, false),
+            'b' => (Button::KeyB, false),
+            'c' => (Button::KeyC, false),
+            'd' => (Button::KeyD, false),
+            'e' => (Button::KeyE, false),
+            'f' => (Button::KeyF, false),
+            'g' => (Button::KeyG, false),
+            'h' => (Button::KeyH, false),
+            'i' => (Button::KeyI, false),
+            'j' => (Button::KeyJ, false),
+            'k' => (Button::KeyK, false),
+            'l' => (Button::KeyL, false),
+            'm' => (Button::KeyM, false),
+            'n' => (Button::KeyN, false),
+            'o' => (Button::KeyO, false),
+            'p' => (Button::KeyP, false),
+            'q' => (Button::KeyQ, false),
+            'r' => (Button::KeyR, false),
+            's' => (Button::KeyS, false),
+            't' => (Button::KeyT, false),
+            'u' => (Button::KeyU, false),
+            'v' => (Button::KeyV, false),
+            'w' => (Button::KeyW, false),
+            'x' => (Button::KeyX, false),
+            'y' => (Button::KeyY, false),
+            'z' => (Button::KeyZ, false),
+            'A' => (Button::KeyA, true),
+            'B' => (Button::KeyB, true),
+            'C' => (Button::KeyC, true),
+            'D' => (Button::KeyD, true),
+            'E' => (Button::KeyE, true),
+            'F' => (Button::KeyF, true),
+            'G' => (Button::KeyG, true),
+            'H' => (Button::KeyH, true),
+            'I' => (Button::KeyI, true),
+            'J' => (Button::KeyJ, true),
+            'K' => (Button::KeyK, true),
+            'L' => (Button::KeyL, true),
+            'M' => (Button::KeyM, true),
+            'N' => (Button::KeyN, true),
+            'O' => (Button::KeyO, true),
+            'P' => (Button::KeyP, true),
+            'Q' => (Button::KeyQ, true),
+            'R' => (Button::KeyR, true),
+            'S' => (Button::KeyS, true),
+            'T' => (Button::KeyT, true),
+            'U' => (Button::KeyU, true),
+            'V' => (Button::KeyV, true),
+            'W' => (Button::KeyW, true),
+            'X' => (Button::KeyX, true),
+            'Y' => (Button::KeyY, true),
+            'Z' => (Button::KeyZ, true),
+            '0' => (Button::Num0, false),
+            '1' => (Button::Num1, false),
+            '2' => (Button::Num2, false),
+            '3' => (Button::Num3, false),
+            '4' => (Button::Num4, false),
+            '5' => (Button::Num5, false),
+            '6' => (Button::Num6, false),
+            '7' => (Button::Num7, false),
+            '8' => (Button::Num8, false),
+            '9' => (Button::Num9, false),
+            '!' => (Button::Num1, true),
+            '@' => (Button::Num2, true),
+            '#' => (Button::Num3, true),
+            '$' => (Button::Num4, true),
+            '%' => (Button::Num5, true),
+            '^' => (Button::Num6, true),
+            '&' => (Button::Num7, true),
+            '*' => (Button::Num8, true),
+            '(' => (Button::Num9, true),
+            ')' => (Button::Num0, true),
+            ' ' => (Button::Space, false),
+            '\n' => (Button::Return, false),
+            '\t' => (Button::Tab, false),
+            '-' => (Button::Minus, false),
+            '_' => (Button::Minus, true),
+            '=' => (Button::Equal, false),
+            '+' => (Button::Equal, true),
+            '[' => (Button::LeftBracket, false),
+            '{' => (Button::LeftBracket, true),
+            ']' => (Button::RightBracket, false),
+            '}' => (Button::RightBracket, true),
+            '\\' => (Button::BackSlash, false),
+            '|' => (Button::BackSlash, true),
+            ';' => (Button::SemiColon, false),
+            ':' => (Button::SemiColon, true),
+            '\'' => (Button::Quote, false),
+            '"' => (Button::Quote, true),
+            ',' => (Button::Comma, false),
+            '<' => (Button::Comma, true),
+            '.' => (Button::Dot, false),
+            '>' => (Button::Dot, true),
+            '/' => (Button::Slash, false),
+            '?' => (Button::Slash, true),
+            '`' => (Button::BackQuote, false),
+            '~' => (Button::BackQuote, true),
+            _ => return None,
+        };
+
+        let mut buttons: HeldButtons = IndexSet::new();
+        if shift {
+            buttons.insert(Button::ShiftLeft);
+        }
+        buttons.insert(button);
+        Some(buttons)
+    }
+
     pub fn rdev_mouse_button(&self) -> Option<rdev::Button> {
         match self {
             Button::MouseLeft => Some(rdev::Button::Left),
             Button::MouseRight => Some(rdev::Button::Right),
             Button::MouseMiddle => Some(rdev::Button::Middle),
+            #[cfg(target_os = "windows")]
+            Button::MouseBack => Some(rdev::Button::Unknown(1)), // XBUTTON1
+            #[cfg(target_os = "windows")]
+            Button::MouseForward => Some(rdev::Button::Unknown(2)), // XBUTTON2
+            #[cfg(not(target_os = "windows"))]
+            Button::MouseBack => Some(rdev::Button::Unknown(8)),
+            #[cfg(not(target_os = "windows"))]
+            Button::MouseForward => Some(rdev::Button::Unknown(9)),
+            Button::MouseButton(button) => Some(rdev::Button::Unknown(*button)),
             _ => None,
         }
     }
 
+    // The platform key code of a media key, which rdev does not know about
+    fn media_key_code(&self) -> Option<u32> {
+        #[cfg(target_os = "windows")]
+        let code = match self {
+            Button::VolumeUp => 0xAF, // VK_VOLUME_UP
+            Button::VolumeDown => 0xAE, // VK_VOLUME_DOWN
+            Button::VolumeMute => 0xAD, // VK_VOLUME_MUTE
+            Button::MediaPlayPause => 0xB3, // VK_MEDIA_PLAY_PAUSE
+            Button::MediaNext => 0xB0, // VK_MEDIA_NEXT_TRACK
+            Button::MediaPrevious => 0xB1, // VK_MEDIA_PREV_TRACK
+            Button::MediaStop => 0xB2, // VK_MEDIA_STOP
+            _ => return None,
+        };
+
+        // macOS only has key codes for the volume keys, the other media keys are system events
+        #[cfg(target_os = "macos")]
+        let code = match self {
+            Button::VolumeUp => 0x48, // kVK_VolumeUp
+            Button::VolumeDown => 0x49, // kVK_VolumeDown
+            Button::VolumeMute => 0x4A, // kVK_Mute
+            _ => return None,
+        };
+
+        // X11 keycodes are the evdev codes plus 8
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let code = match self {
+            Button::VolumeUp | Button::VolumeDown | Button::VolumeMute |
+            Button::MediaPlayPause | Button::MediaNext | Button::MediaPrevious | Button::MediaStop => {
+                u32::from(self.evdev_code()?) + 8
+            },
+            _ => return None,
+        };
+
+        Some(code)
+    }
+
     pub fn rdev_key(&self) -> Option<rdev::Key> {
         match self {
-            Button::MouseLeft | Button::MouseRight | Button::MouseMiddle => None,
+            Button::MouseLeft | Button::MouseRight | Button::MouseMiddle |
+            Button::MouseBack | Button::MouseForward | Button::MouseButton(_) | Button::Char(_) => None,
+
+            Button::VolumeUp | Button::VolumeDown | Button::VolumeMute |
+            Button::MediaPlayPause | Button::MediaNext | Button::MediaPrevious | Button::MediaStop => {
+                self.media_key_code().map(rdev::Key::Unknown)
+            },
+            Button::KeyCode(code) => Some(rdev::Key::Unknown(*code)),
 
             Button::UpArrow => Some(rdev::Key::UpArrow),
             Button::RightArrow => Some(rdev::Key::RightArrow),
@@ -347,6 +590,142 @@ impl Button {
             Button::KpReturn => Some(rdev::Key::KpReturn),
         }
     }
+
+    /**
+     * The Linux input event code (see linux/input-event-codes.h) of this button. Characters do not
+     * have a code, they are typed using a sequence of keys.
+     */
+    pub fn evdev_code(&self) -> Option<u16> {
+        let code = match self {
+            Button::MouseBack => 0x113, // BTN_SIDE
+            Button::MouseForward => 0x114, // BTN_EXTRA
+            Button::VolumeUp => 115, // KEY_VOLUMEUP
+            Button::VolumeDown => 114, // KEY_VOLUMEDOWN
+            Button::VolumeMute => 113, // KEY_MUTE
+            Button::MediaPlayPause => 164, // KEY_PLAYPAUSE
+            Button::MediaNext => 163, // KEY_NEXTSONG
+            Button::MediaPrevious => 165, // KEY_PREVIOUSSONG
+            Button::MediaStop => 166, // KEY_STOPCD
+            // X11 keycodes are the evdev codes plus 8
+            Button::KeyCode(code) => {
+                return code.checked_sub(8)
+                    .and_then(|code| u16::try_from(code).ok())
+                    .filter(|code| is_evdev_keyboard_key(*code));
+            },
+            // X11 buttons 8 to 12 are BTN_SIDE to BTN_TASK
+            Button::MouseButton(button @ 8..=12) => 0x113 + u16::from(*button - 8),
+            Button::MouseButton(_) | Button::Char(_) => return None,
+            Button::MouseLeft => 0x110, // BTN_LEFT
+            Button::MouseRight => 0x111, // BTN_RIGHT
+            Button::MouseMiddle => 0x112, // BTN_MIDDLE
+            Button::UpArrow => 103, // KEY_UP
+            Button::RightArrow => 106, // KEY_RIGHT
+            Button::DownArrow => 108, // KEY_DOWN
+            Button::LeftArrow => 105, // KEY_LEFT
+            Button::ShiftLeft => 42, // KEY_LEFTSHIFT
+            Button::ShiftRight => 54, // KEY_RIGHTSHIFT
+            Button::Function => 464, // KEY_FN
+            Button::Escape => 1, // KEY_ESC
+            Button::Tab => 15, // KEY_TAB
+            Button::Backspace => 14, // KEY_BACKSPACE
+            Button::Return => 28, // KEY_ENTER
+            Button::CapsLock => 58, // KEY_CAPSLOCK
+            Button::Insert => 110, // KEY_INSERT
+            Button::Delete => 111, // KEY_DELETE
+            Button::Home => 102, // KEY_HOME
+            Button::End => 107, // KEY_END
+            Button::PageUp => 104, // KEY_PAGEUP
+            Button::PageDown => 109, // KEY_PAGEDOWN
+            Button::Space => 57, // KEY_SPACE
+            Button::KeyA => 30, // KEY_A
+            Button::KeyB => 48, // KEY_B
+            Button::KeyC => 46, // KEY_C
+            Button::KeyD => 32, // KEY_D
+            Button::KeyE => 18, // KEY_E
+            Button::KeyF => 33, // KEY_F
+            Button::KeyG => 34, // KEY_G
+            Button::KeyH => 35, // KEY_H
+            Button::KeyI => 23, // KEY_I
+            Button::KeyJ => 36, // KEY_J
+            Button::KeyK => 37, // KEY_K
+            Button::KeyL => 38, // KEY_L
+            Button::KeyM => 50, // KEY_M
+            Button::KeyN => 49, // KEY_N
+            Button::KeyO => 24, // KEY_O
+            Button::KeyP => 25, // KEY_P
+            Button::KeyQ => 16, // KEY_Q
+            Button::KeyR => 19, // KEY_R
+            Button::KeyS => 31, // KEY_S
+            Button::KeyT => 20, // KEY_T
+            Button::KeyU => 22, // KEY_U
+            Button::KeyV => 47, // KEY_V
+            Button::KeyW => 17, // KEY_W
+            Button::KeyX => 45, // KEY_X
+            Button::KeyY => 21, // KEY_Y
+            Button::KeyZ => 44, // KEY_Z
+            Button::BackQuote => 41, // KEY_GRAVE
+            Button::Num0 => 11, // KEY_0
+            Button::Num1 => 2, // KEY_1
+            Button::Num2 => 3, // KEY_2
+            Button::Num3 => 4, // KEY_3
+            Button::Num4 => 5, // KEY_4
+            Button::Num5 => 6, // KEY_5
+            Button::Num6 => 7, // KEY_6
+            Button::Num7 => 8, // KEY_7
+            Button::Num8 => 9, // KEY_8
+            Button::Num9 => 10, // KEY_9
+            Button::Minus => 12, // KEY_MINUS
+            Button::Equal => 13, // KEY_EQUAL
+            Button::F1 => 59, // KEY_F1
+            Button::F2 => 60, // KEY_F2
+            Button::F3 => 61, // KEY_F3
+            Button::F4 => 62, // KEY_F4
+            Button::F5 => 63, // KEY_F5
+            Button::F6 => 64, // KEY_F6
+            Button::F7 => 65, // KEY_F7
+            Button::F8 => 66, // KEY_F8
+            Button::F9 => 67, // KEY_F9
+            Button::F10 => 68, // KEY_F10
+            Button::F11 => 87, // KEY_F11
+            Button::F12 => 88, // KEY_F12
+            Button::Alt => 56, // KEY_LEFTALT
+            Button::AltGr => 100, // KEY_RIGHTALT
+            Button::ControlLeft => 29, // KEY_LEFTCTRL
+            Button::ControlRight => 97, // KEY_RIGHTCTRL
+            Button::MetaLeft => 125, // KEY_LEFTMETA
+            Button::MetaRight => 126, // KEY_RIGHTMETA
+            Button::BackSlash => 43, // KEY_BACKSLASH
+            Button::Comma => 51, // KEY_COMMA
+            Button::Dot => 52, // KEY_DOT
+            Button::LeftBracket => 26, // KEY_LEFTBRACE
+            Button::Quote => 40, // KEY_APOSTROPHE
+            Button::RightBracket => 27, // KEY_RIGHTBRACE
+            Button::SemiColon => 39, // KEY_SEMICOLON
+            Button::Slash => 53, // KEY_SLASH
+            Button::PrintScreen => 99, // KEY_SYSRQ
+            Button::ScrollLock => 70, // KEY_SCROLLLOCK
+            Button::Pause => 119, // KEY_PAUSE
+            Button::NumLock => 69, // KEY_NUMLOCK
+            Button::Kp0 => 82, // KEY_KP0
+            Button::Kp1 => 79, // KEY_KP1
+            Button::Kp2 => 80, // KEY_KP2
+            Button::Kp3 => 81, // KEY_KP3
+            Button::Kp4 => 75, // KEY_KP4
+            Button::Kp5 => 76, // KEY_KP5
+            Button::Kp6 => 77, // KEY_KP6
+            Button::Kp7 => 71, // KEY_KP7
+            Button::Kp8 => 72, // KEY_KP8
+            Button::Kp9 => 73, // KEY_KP9
+            Button::KpDelete => 83, // KEY_KPDOT
+            Button::KpDivide => 98, // KEY_KPSLASH
+            Button::KpMinus => 74, // KEY_KPMINUS
+            Button::KpMultiply => 55, // KEY_KPASTERISK
+            Button::KpPlus => 78, // KEY_KPPLUS
+            Button::KpReturn => 96, // KEY_KPENTER
+        };
+
+        Some(code)
+    }
 }
 
 
@@ -356,6 +735,18 @@ impl std::fmt::Display for Button {
             Button::MouseLeft => "Mouse Left",
             Button::MouseRight => "Mouse Right",
             Button::MouseMiddle => "Mouse Middle",
+            Button::MouseBack => "Mouse Back",
+            Button::MouseForward => "Mouse Forward",
+            Button::VolumeUp => "Volume Up",
+            Button::VolumeDown => "Volume Down",
+            Button::VolumeMute => "Volume Mute",
+            Button::MediaPlayPause => "Play/Pause",
+            Button::MediaNext => "Next Track",
+            Button::MediaPrevious => "Previous Track",
+            Button::MediaStop => "Stop",
+            Button::KeyCode(code) => return write!(f, "Key Code {}", code),
+            Button::MouseButton(button) => return write!(f, "Mouse Button {}", button),
+            Button::Char(character) => return write!(f, "Character {}", character),
 
             Button::Alt => "Alt",
             Button::AltGr => "AltGr",
@@ -467,12 +858,160 @@ impl std::fmt::Display for Button {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftShoulder,
+    RightShoulder,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+}
+
+pub const GAMEPAD_BUTTONS: [GamepadButton; 10] = [
+    GamepadButton::South,
+    GamepadButton::East,
+    GamepadButton::North,
+    GamepadButton::West,
+    GamepadButton::LeftShoulder,
+    GamepadButton::RightShoulder,
+    GamepadButton::Select,
+    GamepadButton::Start,
+    GamepadButton::LeftStick,
+    GamepadButton::RightStick,
+];
+
+impl GamepadButton {
+    /**
+     * The Linux input event code (see linux/input-event-codes.h) of this button.
+     */
+    pub fn evdev_code(&self) -> u16 {
+        match self {
+            GamepadButton::South => 0x130, // BTN_SOUTH
+            GamepadButton::East => 0x131, // BTN_EAST
+            GamepadButton::North => 0x133, // BTN_NORTH
+            GamepadButton::West => 0x134, // BTN_WEST
+            GamepadButton::LeftShoulder => 0x136, // BTN_TL
+            GamepadButton::RightShoulder => 0x137, // BTN_TR
+            GamepadButton::Select => 0x13a, // BTN_SELECT
+            GamepadButton::Start => 0x13b, // BTN_START
+            GamepadButton::LeftStick => 0x13d, // BTN_THUMBL
+            GamepadButton::RightStick => 0x13e, // BTN_THUMBR
+        }
+    }
+}
+
+impl std::fmt::Display for GamepadButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let result = match self {
+            GamepadButton::South => "South (A / Cross)",
+            GamepadButton::East => "East (B / Circle)",
+            GamepadButton::North => "North (Y / Triangle)",
+            GamepadButton::West => "West (X / Square)",
+            GamepadButton::LeftShoulder => "Left Shoulder",
+            GamepadButton::RightShoulder => "Right Shoulder",
+            GamepadButton::Select => "Select",
+            GamepadButton::Start => "Start",
+            GamepadButton::LeftStick => "Left Stick Press",
+            GamepadButton::RightStick => "Right Stick Press",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+pub const GAMEPAD_AXES: [GamepadAxis; 6] = [
+    GamepadAxis::LeftStickX,
+    GamepadAxis::LeftStickY,
+    GamepadAxis::RightStickX,
+    GamepadAxis::RightStickY,
+    GamepadAxis::LeftTrigger,
+    GamepadAxis::RightTrigger,
+];
+
+impl GamepadAxis {
+    /**
+     * The Linux input event code (see linux/input-event-codes.h) of this axis.
+     */
+    pub fn evdev_code(&self) -> u16 {
+        match self {
+            GamepadAxis::LeftStickX => 0x00, // ABS_X
+            GamepadAxis::LeftStickY => 0x01, // ABS_Y
+            GamepadAxis::RightStickX => 0x03, // ABS_RX
+            GamepadAxis::RightStickY => 0x04, // ABS_RY
+            GamepadAxis::LeftTrigger => 0x02, // ABS_Z
+            GamepadAxis::RightTrigger => 0x05, // ABS_RZ
+        }
+    }
+
+    // The minimum and maximum value, the same as most USB gamepads
+    pub fn range(&self) -> (i32, i32) {
+        match self {
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => (0, 255),
+            _ => (-32768, 32767),
+        }
+    }
+}
+
 pub type HeldButtons = IndexSet<Button>;
 
+// Buttons that are tapped again and again, see InputSimCommand::SetRepeat
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatTap {
+    pub buttons: HeldButtons,
+    pub tap_duration: Duration,
+    // time between the start of one tap and the start of the next one
+    pub interval: Duration,
+}
+
 pub enum InputSimCommand {
     SetHeldButtons(HeldButtons),
+    // press the buttons, and release them after the given duration
+    Tap(HeldButtons, Duration),
+    // keep tapping the buttons until this is set to None. changing only the interval does not
+    // restart the repetition
+    SetRepeat(Option<RepeatTap>),
+    // run the steps of a macro, replacing any macro that is still running
+    RunMacro(Vec<MacroStep>),
+    CancelMacro,
+    // turn the scroll wheel this many notches, horizontally and vertically. positive is right and up
+    Scroll(i64, i64),
+    // move the mouse pointer this many pixels per tick, until the velocity is set to zero
+    SetPointerVelocity((f64, f64)),
 }
 
 pub enum BreathInputSimCommand {
     SetConfig(Config),
 }
+
+#[derive(Debug, Clone)]
+pub enum BreathInputSimEvent {
+    // the breath value after filtering, the GUI receives the raw value from the device
+    FilteredBreath(i8),
+    // the number of breath artifacts (such as coughs) that have been ignored so far
+    RejectedArtifacts(u32),
+    // the buttons that remain pressed because of hotkeys in latch mode
+    LatchedButtons(HeldButtons),
+    // the hotkey has been released because it was held for longer than the given duration
+    HoldTimeExceeded(HotkeyConfig, Duration),
+    // the dots and dashes of the morse character that is being entered
+    MorseCode(String),
+    // the direction in which puffing moves the mouse pointer
+    PointerDirection(PointerDirection),
+    // the virtual gamepad has been enabled, but could not be created
+    GamepadError(String),
+}
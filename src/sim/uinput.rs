@@ -0,0 +1,272 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+
+use crate::sim::backend::{InputBackend, type_with_unicode_input};
+use crate::sim::types::{Button, EVDEV_KEY_MAX, GAMEPAD_AXES, GAMEPAD_BUTTONS, GamepadAxis, GamepadButton, is_evdev_keyboard_key};
+
+// linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const BUS_VIRTUAL: u16 = 0x06;
+// BTN_LEFT up to BTN_TASK
+const MOUSE_BUTTON_CODES: std::ops::RangeInclusive<u16> = 0x110..=0x117;
+
+// linux/uinput.h, these are _IO and _IOW('U', ...)
+const UI_DEV_CREATE: u32 = 0x5501;
+const UI_DEV_DESTROY: u32 = 0x5502;
+const UI_DEV_SETUP: u32 = 0x405c5503;
+const UI_SET_EVBIT: u32 = 0x40045564;
+const UI_SET_KEYBIT: u32 = 0x40045565;
+const UI_SET_RELBIT: u32 = 0x40045566;
+const UI_SET_ABSBIT: u32 = 0x40045567;
+const UI_ABS_SETUP: u32 = 0x401c5504;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+const DEVICE_NAME: &[u8] = b"GroovTube Hotkey virtual input";
+const GAMEPAD_NAME: &[u8] = b"GroovTube Hotkey virtual gamepad";
+
+/**
+ * How long to wait after creating the device, before sending events. Input events are lost if
+ * they are sent before the compositor has opened the new device.
+ */
+const DEVICE_SETTLE_TIME: u64 = 200;
+
+#[repr(C)]
+struct InputId {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+#[repr(C)]
+struct UinputSetup {
+    id: InputId,
+    name: [u8; 80],
+    ff_effects_max: u32,
+}
+
+#[repr(C)]
+struct InputAbsInfo {
+    value: i32,
+    minimum: i32,
+    maximum: i32,
+    fuzz: i32,
+    flat: i32,
+    resolution: i32,
+}
+
+#[repr(C)]
+struct UinputAbsSetup {
+    code: u16,
+    absinfo: InputAbsInfo,
+}
+
+#[repr(C)]
+struct InputEvent {
+    time: libc::timeval,
+    event_type: u16,
+    code: u16,
+    value: i32,
+}
+
+fn ioctl(file: &File, request: u32, value: libc::c_int) -> io::Result<()> {
+    // the type of the request argument differs between libc implementations
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, value) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/**
+ * A virtual input device in the kernel, see https://www.kernel.org/doc/html/latest/input/uinput.html
+ */
+struct UinputDevice {
+    file: Mutex<File>,
+}
+
+impl UinputDevice {
+    // Create a device with the given name, `configure` enables the event types and codes the device supports
+    fn create(name: &[u8], configure: impl FnOnce(&File) -> io::Result<()>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)?;
+
+        configure(&file)?;
+
+        let mut setup = UinputSetup {
+            id: InputId { bustype: BUS_VIRTUAL, vendor: 0, product: 0, version: 1 },
+            name: [0; 80],
+            ff_effects_max: 0,
+        };
+        setup.name[..name.len()].copy_from_slice(name);
+
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), UI_DEV_SETUP as _, &setup as *const UinputSetup) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        ioctl(&file, UI_DEV_CREATE, 0)?;
+        info!("Created uinput device {}", String::from_utf8_lossy(name));
+
+        Ok(UinputDevice { file: Mutex::new(file) })
+    }
+
+    // Write the events followed by a SYN_REPORT, so that they are handled as one update
+    fn emit(&self, events: &[(u16, u16, i32)]) {
+        let mut file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+
+        for &(event_type, code, value) in events.iter().chain([(EV_SYN, SYN_REPORT, 0)].iter()) {
+            let event = InputEvent {
+                time: libc::timeval { tv_sec: 0, tv_usec: 0 },
+                event_type,
+                code,
+                value,
+            };
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    &event as *const InputEvent as *const u8,
+                    std::mem::size_of::<InputEvent>(),
+                )
+            };
+
+            if let Err(err) = file.write_all(bytes) {
+                warn!("Failed to write to uinput device: {}", err);
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        let file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = ioctl(&file, UI_DEV_DESTROY, 0) {
+            warn!("Failed to destroy uinput device: {}", err);
+        }
+    }
+}
+
+/**
+ * Simulates keyboard and mouse input by creating a virtual input device in the kernel. Unlike rdev
+ * (which uses X11), this also works in a Wayland session. Requires write access to /dev/uinput.
+ */
+pub struct UinputBackend {
+    device: UinputDevice,
+}
+
+impl UinputBackend {
+    pub fn new() -> io::Result<Self> {
+        let device = UinputDevice::create(DEVICE_NAME, |file| {
+            ioctl(file, UI_SET_EVBIT, EV_KEY.into())?;
+            // every keyboard key up to KEY_MAX, so that Function and Button::KeyCode can be sent too
+            let key_codes = (1..=EVDEV_KEY_MAX).filter(|code| is_evdev_keyboard_key(*code));
+            for code in key_codes.chain(MOUSE_BUTTON_CODES) {
+                ioctl(file, UI_SET_KEYBIT, code.into())?;
+            }
+
+            ioctl(file, UI_SET_EVBIT, EV_REL.into())?;
+            for code in [REL_X, REL_Y, REL_WHEEL, REL_HWHEEL] {
+                ioctl(file, UI_SET_RELBIT, code.into())?;
+            }
+            Ok(())
+        })?;
+        thread::sleep(Duration::from_millis(DEVICE_SETTLE_TIME));
+
+        Ok(UinputBackend { device })
+    }
+
+    fn emit_relative(&self, axes: &[(u16, i64)]) {
+        let events: Vec<(u16, u16, i32)> = axes.iter()
+            .filter(|(_, delta)| *delta != 0)
+            .map(|&(code, delta)| (EV_REL, code, delta.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32))
+            .collect();
+
+        if !events.is_empty() {
+            self.device.emit(&events);
+        }
+    }
+}
+
+impl InputBackend for UinputBackend {
+    fn press(&self, button: &Button) {
+        match (button, button.evdev_code()) {
+            (Button::Char(character), _) => type_with_unicode_input(self, *character),
+            (_, Some(code)) => self.device.emit(&[(EV_KEY, code, 1)]),
+            (_, None) => warn!("{} can not be pressed using uinput", button),
+        }
+    }
+
+    fn release(&self, button: &Button) {
+        if let Some(code) = button.evdev_code() {
+            self.device.emit(&[(EV_KEY, code, 0)]);
+        }
+    }
+
+    fn move_pointer(&self, dx: f64, dy: f64) {
+        self.emit_relative(&[(REL_X, dx.round() as i64), (REL_Y, dy.round() as i64)]);
+    }
+
+    fn scroll(&self, delta_x: i64, delta_y: i64) {
+        self.emit_relative(&[(REL_HWHEEL, delta_x), (REL_WHEEL, delta_y)]);
+    }
+}
+
+/**
+ * A virtual gamepad with two analog sticks, two analog triggers and the usual buttons.
+ */
+pub struct UinputGamepad {
+    device: UinputDevice,
+}
+
+impl UinputGamepad {
+    pub fn new() -> io::Result<Self> {
+        let device = UinputDevice::create(GAMEPAD_NAME, |file| {
+            ioctl(file, UI_SET_EVBIT, EV_KEY.into())?;
+            for button in GAMEPAD_BUTTONS {
+                ioctl(file, UI_SET_KEYBIT, button.evdev_code().into())?;
+            }
+
+            ioctl(file, UI_SET_EVBIT, EV_ABS.into())?;
+            for axis in GAMEPAD_AXES {
+                ioctl(file, UI_SET_ABSBIT, axis.evdev_code().into())?;
+
+                let (minimum, maximum) = axis.range();
+                let setup = UinputAbsSetup {
+                    code: axis.evdev_code(),
+                    absinfo: InputAbsInfo { value: 0, minimum, maximum, fuzz: 0, flat: 0, resolution: 0 },
+                };
+                let result = unsafe { libc::ioctl(file.as_raw_fd(), UI_ABS_SETUP as _, &setup as *const UinputAbsSetup) };
+                if result < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(UinputGamepad { device })
+    }
+
+    pub fn set_axis(&self, axis: GamepadAxis, value: i32) {
+        self.device.emit(&[(EV_ABS, axis.evdev_code(), value)]);
+    }
+
+    pub fn set_button(&self, button: GamepadButton, pressed: bool) {
+        self.device.emit(&[(EV_KEY, button.evdev_code(), i32::from(pressed))]);
+    }
+}